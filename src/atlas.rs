@@ -0,0 +1,635 @@
+use crate::cache::CacheKey;
+use bevy::asset::RenderAssetUsages;
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+
+const L: f32 = 0.25;
+const C: f32 = 0.5;
+const R: f32 = 0.75;
+const PIP_POSITIONS: [&[(f32, f32)]; 9] = [
+    &[(C, C)],
+    &[(R, L), (L, R)],
+    &[(R, L), (C, C), (L, R)],
+    &[(L, L), (R, L), (L, R), (R, R)],
+    &[(L, L), (R, L), (C, C), (L, R), (R, R)],
+    &[(L, L), (L, C), (L, R), (R, L), (R, C), (R, R)],
+    &[(L, L), (L, C), (L, R), (C, C), (R, L), (R, C), (R, R)],
+    &[
+        (L, L),
+        (L, C),
+        (L, R),
+        (C, L),
+        (C, R),
+        (R, L),
+        (R, C),
+        (R, R),
+    ],
+    &[
+        (L, L),
+        (L, C),
+        (L, R),
+        (C, L),
+        (C, C),
+        (C, R),
+        (R, L),
+        (R, C),
+        (R, R),
+    ],
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum Marking {
+    #[default]
+    Pips,
+    Numerals,
+}
+
+#[derive(Resource)]
+pub struct SelectedMarking(pub Marking);
+
+// fonts are glyph tables, so that any of them can be drawn and engraved alike; only BLOCKY
+// ships with the game, which is why there is no font option yet
+#[derive(Clone, Copy)]
+pub struct BitmapFont {
+    pub width: u32,
+    pub height: u32,
+    pub glyph: fn(char) -> Option<&'static [&'static str]>,
+}
+
+#[derive(Clone, Copy)]
+pub struct AtlasSettings {
+    pub marking: Marking,
+    pub font: BitmapFont,
+    // pixels per face, every face occupies a square cell
+    pub resolution: u32,
+    pub pip_radius: f32,
+    // in uv units, which is what parallax_depth_scale expects
+    pub engraving_depth: f32,
+}
+
+pub struct FaceAtlas {
+    pub color: Image,
+    pub normal: Image,
+    pub depth: Image,
+    pub parallax_depth_scale: f32,
+}
+
+impl Marking {
+    pub const ALL: [Marking; 2] = [Marking::Pips, Marking::Numerals];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Marking::Pips => "pips",
+            Marking::Numerals => "numerals",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        Marking::ALL
+            .into_iter()
+            .find(|marking| marking.name() == value)
+            .ok_or_else(|| format!("unknown marking {value}, expected pips or numerals"))
+    }
+}
+
+impl BitmapFont {
+    pub const BLOCKY: BitmapFont = BitmapFont {
+        width: 5,
+        height: 7,
+        glyph: blocky_glyph,
+    };
+}
+
+impl Default for AtlasSettings {
+    fn default() -> Self {
+        AtlasSettings {
+            marking: Marking::default(),
+            font: BitmapFont::BLOCKY,
+            resolution: 256,
+            pip_radius: 0.09,
+            engraving_depth: 0.008,
+        }
+    }
+}
+
+impl AtlasSettings {
+    // one cell per label, labels that are numbers up to 9 are drawn as pips if requested
+    pub fn generate(&self, labels: &[&str]) -> FaceAtlas {
+        let face_count = labels.len() as u32;
+        let (width, height) = (self.resolution * face_count, self.resolution);
+        let mut coverages = vec![0.0; (width * height) as usize];
+        let mut depths = vec![0.0; (width * height) as usize];
+        for (face, label) in labels.iter().enumerate() {
+            let face = face as u32;
+            for y in 0..height {
+                for x in 0..self.resolution {
+                    let uv = Vec2::new(
+                        (x as f32 + 0.5) / self.resolution as f32,
+                        (y as f32 + 0.5) / self.resolution as f32,
+                    );
+                    let (coverage, depth) = self.sample(label, uv);
+                    let index = (y * width + face * self.resolution + x) as usize;
+                    coverages[index] = coverage;
+                    depths[index] = depth;
+                }
+            }
+        }
+
+        let mut color = Vec::with_capacity(depths.len() * 4);
+        let mut normal = Vec::with_capacity(depths.len() * 4);
+        let mut depth = Vec::with_capacity(depths.len());
+        let height_at = |x: i64, y: i64| {
+            let x = x.clamp(0, width as i64 - 1);
+            let y = y.clamp(0, height as i64 - 1);
+            -depths[(y * width as i64 + x) as usize] * self.engraving_depth
+        };
+        for y in 0..height as i64 {
+            for x in 0..width as i64 {
+                let index = (y * width as i64 + x) as usize;
+                let (coverage, d) = (coverages[index], depths[index]);
+                let shade = ((1.0 - coverage) * 255.0) as u8;
+                color.extend([shade, shade, shade, 255]);
+
+                // heights are in uv units, so are the texel distances
+                let texel = 1.0 / self.resolution as f32;
+                let dx = (height_at(x + 1, y) - height_at(x - 1, y)) / (2.0 * texel);
+                let dy = (height_at(x, y + 1) - height_at(x, y - 1)) / (2.0 * texel);
+                // image rows grow downwards, while the normal map expects y up
+                let n = Vec3::new(-dx, dy, 1.0).normalize();
+                normal.extend([
+                    ((n.x + 1.0) / 2.0 * 255.0) as u8,
+                    ((n.y + 1.0) / 2.0 * 255.0) as u8,
+                    ((n.z + 1.0) / 2.0 * 255.0) as u8,
+                    255,
+                ]);
+                depth.push((d * 255.0) as u8);
+            }
+        }
+
+        let size = Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let image = |data, format| {
+            Image::new(
+                size,
+                TextureDimension::D2,
+                data,
+                format,
+                RenderAssetUsages::default(),
+            )
+        };
+        FaceAtlas {
+            color: image(color, TextureFormat::Rgba8UnormSrgb),
+            normal: image(normal, TextureFormat::Rgba8Unorm),
+            depth: image(depth, TextureFormat::R8Unorm),
+            parallax_depth_scale: self.engraving_depth,
+        }
+    }
+
+    // extends the key by everything the depth of the labels depends on; fonts are functions,
+    // so the glyphs the labels use are hashed instead
+    pub fn key(&self, key: CacheKey, labels: &[&str]) -> CacheKey {
+        let key = key
+            .bytes(&[self.marking as u8])
+            .bytes(&self.resolution.to_le_bytes())
+            .floats(&[self.pip_radius, self.engraving_depth])
+            .bytes(&[self.font.width as u8, self.font.height as u8]);
+        labels.iter().fold(key, |key, label| {
+            label
+                .chars()
+                .filter_map(self.font.glyph)
+                .flatten()
+                .fold(key.bytes(label.as_bytes()), |key, row| {
+                    key.bytes(row.as_bytes())
+                })
+        })
+    }
+
+    // the depth the atlas bakes at a uv of the whole atlas, from 0 on the surface to 1 at the
+    // deepest point, so that meshes can be engraved with exactly the same markings
+    pub fn depth_at(&self, labels: &[&str], uv: Vec2) -> f32 {
+        let cells = labels.len() as f32;
+        let face = ((uv.x * cells) as usize).min(labels.len().saturating_sub(1));
+        let Some(label) = labels.get(face) else {
+            return 0.0;
+        };
+        self.sample(label, Vec2::new(uv.x * cells - face as f32, uv.y))
+            .1
+    }
+
+    // labels that are numbers up to 9 are drawn as pips if requested, uv is within the cell
+    fn sample(&self, label: &str, uv: Vec2) -> (f32, f32) {
+        let pips = label
+            .parse::<usize>()
+            .ok()
+            .filter(|value| (1..=9).contains(value));
+        match (self.marking, pips) {
+            (Marking::Pips, Some(pips)) => self.pip_sample(pips, uv),
+            _ => self.text_sample(label, uv),
+        }
+    }
+
+    // pips are spherical dimples, deepest in their center
+    fn pip_sample(&self, pips: usize, uv: Vec2) -> (f32, f32) {
+        let texel = 1.0 / self.resolution as f32;
+        PIP_POSITIONS[pips - 1]
+            .iter()
+            .map(|(x, y)| {
+                let distance = uv.distance(Vec2::new(*x, *y)) / self.pip_radius;
+                let coverage = ((1.0 - distance) * self.pip_radius / texel + 0.5).clamp(0.0, 1.0);
+                (coverage, (1.0 - distance * distance).max(0.0).sqrt())
+            })
+            .fold((0.0, 0.0), |(c1, d1), (c2, d2)| (c1.max(c2), d1.max(d2)))
+    }
+
+    // text is engraved with a flat bottom and supersampled edges
+    fn text_sample(&self, text: &str, uv: Vec2) -> (f32, f32) {
+        const SAMPLES: u32 = 4;
+        let texel = 1.0 / self.resolution as f32;
+        let mut covered = 0;
+        for sy in 0..SAMPLES {
+            for sx in 0..SAMPLES {
+                let offset = Vec2::new(
+                    (sx as f32 + 0.5) / SAMPLES as f32 - 0.5,
+                    (sy as f32 + 0.5) / SAMPLES as f32 - 0.5,
+                );
+                if self.text_covers(text, uv + offset * texel) {
+                    covered += 1;
+                }
+            }
+        }
+        let coverage = covered as f32 / (SAMPLES * SAMPLES) as f32;
+        (coverage, coverage)
+    }
+
+    fn text_covers(&self, text: &str, uv: Vec2) -> bool {
+        // underline 6 and 9 so they can be told apart
+        let underlined = text == "6" || text == "9";
+        let font = self.font;
+        let glyphs = text.chars().count() as u32;
+        if glyphs == 0 {
+            return false;
+        }
+        let columns = glyphs * (font.width + 1) - 1;
+        let rows = font.height + if underlined { 2 } else { 0 };
+        // the text is half a face high, or narrower if it would not fit
+        let cell = (0.5 / rows as f32).min(0.7 / columns as f32);
+        let origin = Vec2::new(
+            0.5 - columns as f32 * cell / 2.0,
+            0.5 - rows as f32 * cell / 2.0,
+        );
+        let grid = (uv - origin) / cell;
+        if grid.x < 0.0 || grid.y < 0.0 {
+            return false;
+        }
+        let (column, row) = (grid.x as u32, grid.y as u32);
+        if column >= columns || row >= rows {
+            return false;
+        }
+        if row >= font.height {
+            return row == rows - 1;
+        }
+        if column % (font.width + 1) == font.width {
+            return false;
+        }
+        let character = text
+            .chars()
+            .nth((column / (font.width + 1)) as usize)
+            .expect("character");
+        let Some(glyph) = (font.glyph)(character) else {
+            return false;
+        };
+        glyph[row as usize].as_bytes()[(column % (font.width + 1)) as usize] == b'#'
+    }
+}
+
+// every glyph is 5 columns wide and 7 rows high
+const BLOCKY_GLYPHS: &[(char, [&str; 7])] = &[
+    (
+        '0',
+        [
+            ".###.", "#...#", "#..##", "#.#.#", "##..#", "#...#", ".###.",
+        ],
+    ),
+    (
+        '1',
+        [
+            "..#..", ".##..", "..#..", "..#..", "..#..", "..#..", ".###.",
+        ],
+    ),
+    (
+        '2',
+        [
+            ".###.", "#...#", "....#", "...#.", "..#..", ".#...", "#####",
+        ],
+    ),
+    (
+        '3',
+        [
+            "#####", "...#.", "..#..", "...#.", "....#", "#...#", ".###.",
+        ],
+    ),
+    (
+        '4',
+        [
+            "...#.", "..##.", ".#.#.", "#..#.", "#####", "...#.", "...#.",
+        ],
+    ),
+    (
+        '5',
+        [
+            "#####", "#....", "####.", "....#", "....#", "#...#", ".###.",
+        ],
+    ),
+    (
+        '6',
+        [
+            "..##.", ".#...", "#....", "####.", "#...#", "#...#", ".###.",
+        ],
+    ),
+    (
+        '7',
+        [
+            "#####", "....#", "...#.", "..#..", ".#...", ".#...", ".#...",
+        ],
+    ),
+    (
+        '8',
+        [
+            ".###.", "#...#", "#...#", ".###.", "#...#", "#...#", ".###.",
+        ],
+    ),
+    (
+        '9',
+        [
+            ".###.", "#...#", "#...#", ".####", "....#", "...#.", ".##..",
+        ],
+    ),
+    (
+        'A',
+        [
+            ".###.", "#...#", "#...#", "#####", "#...#", "#...#", "#...#",
+        ],
+    ),
+    (
+        'B',
+        [
+            "####.", "#...#", "#...#", "####.", "#...#", "#...#", "####.",
+        ],
+    ),
+    (
+        'C',
+        [
+            ".###.", "#...#", "#....", "#....", "#....", "#...#", ".###.",
+        ],
+    ),
+    (
+        'D',
+        [
+            "####.", "#...#", "#...#", "#...#", "#...#", "#...#", "####.",
+        ],
+    ),
+    (
+        'E',
+        [
+            "#####", "#....", "#....", "####.", "#....", "#....", "#####",
+        ],
+    ),
+    (
+        'F',
+        [
+            "#####", "#....", "#....", "####.", "#....", "#....", "#....",
+        ],
+    ),
+    (
+        'G',
+        [
+            ".###.", "#...#", "#....", "#.###", "#...#", "#...#", ".####",
+        ],
+    ),
+    (
+        'H',
+        [
+            "#...#", "#...#", "#...#", "#####", "#...#", "#...#", "#...#",
+        ],
+    ),
+    (
+        'I',
+        [
+            ".###.", "..#..", "..#..", "..#..", "..#..", "..#..", ".###.",
+        ],
+    ),
+    (
+        'J',
+        [
+            "..###", "...#.", "...#.", "...#.", "...#.", "#..#.", ".##..",
+        ],
+    ),
+    (
+        'K',
+        [
+            "#...#", "#..#.", "#.#..", "##...", "#.#..", "#..#.", "#...#",
+        ],
+    ),
+    (
+        'L',
+        [
+            "#....", "#....", "#....", "#....", "#....", "#....", "#####",
+        ],
+    ),
+    (
+        'M',
+        [
+            "#...#", "##.##", "#.#.#", "#.#.#", "#...#", "#...#", "#...#",
+        ],
+    ),
+    (
+        'N',
+        [
+            "#...#", "#...#", "##..#", "#.#.#", "#..##", "#...#", "#...#",
+        ],
+    ),
+    (
+        'O',
+        [
+            ".###.", "#...#", "#...#", "#...#", "#...#", "#...#", ".###.",
+        ],
+    ),
+    (
+        'P',
+        [
+            "####.", "#...#", "#...#", "####.", "#....", "#....", "#....",
+        ],
+    ),
+    (
+        'Q',
+        [
+            ".###.", "#...#", "#...#", "#...#", "#.#.#", "#..#.", ".##.#",
+        ],
+    ),
+    (
+        'R',
+        [
+            "####.", "#...#", "#...#", "####.", "#.#..", "#..#.", "#...#",
+        ],
+    ),
+    (
+        'S',
+        [
+            ".####", "#....", "#....", ".###.", "....#", "....#", "####.",
+        ],
+    ),
+    (
+        'T',
+        [
+            "#####", "..#..", "..#..", "..#..", "..#..", "..#..", "..#..",
+        ],
+    ),
+    (
+        'U',
+        [
+            "#...#", "#...#", "#...#", "#...#", "#...#", "#...#", ".###.",
+        ],
+    ),
+    (
+        'V',
+        [
+            "#...#", "#...#", "#...#", "#...#", "#...#", ".#.#.", "..#..",
+        ],
+    ),
+    (
+        'W',
+        [
+            "#...#", "#...#", "#...#", "#.#.#", "#.#.#", "#.#.#", ".#.#.",
+        ],
+    ),
+    (
+        'X',
+        [
+            "#...#", "#...#", ".#.#.", "..#..", ".#.#.", "#...#", "#...#",
+        ],
+    ),
+    (
+        'Y',
+        [
+            "#...#", "#...#", ".#.#.", "..#..", "..#..", "..#..", "..#..",
+        ],
+    ),
+    (
+        'Z',
+        [
+            "#####", "....#", "...#.", "..#..", ".#...", "#....", "#####",
+        ],
+    ),
+    (
+        '+',
+        [
+            ".....", "..#..", "..#..", "#####", "..#..", "..#..", ".....",
+        ],
+    ),
+    (
+        '-',
+        [
+            ".....", ".....", ".....", "#####", ".....", ".....", ".....",
+        ],
+    ),
+    (
+        '?',
+        [
+            ".###.", "#...#", "....#", "...#.", "..#..", ".....", "..#..",
+        ],
+    ),
+    (
+        '!',
+        [
+            "..#..", "..#..", "..#..", "..#..", "..#..", ".....", "..#..",
+        ],
+    ),
+];
+
+fn blocky_glyph(character: char) -> Option<&'static [&'static str]> {
+    // the typographic minus of FATE dice looks just like a hyphen
+    let character = if character == '−' { '-' } else { character };
+    BLOCKY_GLYPHS
+        .iter()
+        .find(|(c, _)| *c == character.to_ascii_uppercase())
+        .map(|(_, rows)| rows.as_slice())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(marking: Marking) -> AtlasSettings {
+        AtlasSettings {
+            marking,
+            resolution: 64,
+            ..default()
+        }
+    }
+
+    #[test]
+    fn every_label_gets_a_square_cell() {
+        for labels in [&["1"][..], &["1", "2", "3", "4", "5", "6"], &["A"; 20]] {
+            let atlas = settings(Marking::Pips).generate(labels);
+            assert_eq!(atlas.color.width(), 64 * labels.len() as u32);
+            assert_eq!(atlas.color.height(), 64);
+            assert_eq!(atlas.depth.size(), atlas.color.size());
+            assert_eq!(atlas.normal.size(), atlas.color.size());
+        }
+        // the center pip of the one is found in the cell of the first label only
+        let atlas = settings(Marking::Pips);
+        let labels = ["1", "", "", "", "", ""];
+        assert_eq!(atlas.depth_at(&labels, Vec2::new(0.5 / 6.0, 0.5)), 1.0);
+        for face in 1..6 {
+            let uv = Vec2::new((face as f32 + 0.5) / 6.0, 0.5);
+            assert_eq!(atlas.depth_at(&labels, uv), 0.0);
+        }
+    }
+
+    #[test]
+    fn pips_and_numerals_are_engraved() {
+        let labels = ["1", "2", "3", "4", "5", "6"];
+        for marking in Marking::ALL {
+            let atlas = settings(marking).generate(&labels);
+            for face in 0..6 {
+                let engraved = (0..64)
+                    .flat_map(|y| (0..64).map(move |x| (face * 64 + x, y)))
+                    .filter(|(x, y)| atlas.depth.data.as_ref().unwrap()[(y * 384 + x) as usize] > 0)
+                    .count();
+                assert!(engraved > 20, "{} of face {face}", marking.name());
+            }
+        }
+        // the top right pip of the two, which numerals do not reach
+        let corner = Vec2::new(0.75 / 6.0 + 1.0 / 6.0, 0.25);
+        assert_eq!(settings(Marking::Pips).depth_at(&labels, corner), 1.0);
+        assert_eq!(settings(Marking::Numerals).depth_at(&labels, corner), 0.0);
+        let center = Vec2::new(0.5 / 6.0, 0.5);
+        assert_eq!(settings(Marking::Numerals).depth_at(&labels, center), 1.0);
+    }
+
+    #[test]
+    fn normals_lean_into_the_pips() {
+        let atlas = settings(Marking::Pips).generate(&["1"]);
+        let normal = |x: u32, y: u32| {
+            let index = ((y * 64 + x) * 4) as usize;
+            let data = atlas.normal.data.as_ref().unwrap();
+            Vec3::new(
+                data[index] as f32,
+                data[index + 1] as f32,
+                data[index + 2] as f32,
+            ) / 127.5
+                - Vec3::ONE
+        };
+        // the pip is a dimple around the center, so its sides face the center; rows grow
+        // downwards, while the normal map has y up
+        assert!(normal(35, 32).x < -0.02);
+        assert!(normal(28, 32).x > 0.02);
+        assert!(normal(32, 35).y > 0.02);
+        assert!(normal(32, 28).y < -0.02);
+        let flat = normal(4, 4);
+        assert!(flat.x.abs() < 0.01 && flat.y.abs() < 0.01 && flat.z > 0.99);
+    }
+}
@@ -0,0 +1,455 @@
+use avian3d::parry::math::Isometry;
+use avian3d::prelude::*;
+use bevy::asset::RenderAssetUsages;
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology, VertexAttributeValues};
+use std::fs;
+use std::path::PathBuf;
+
+// bump this whenever a generator or the file format changes, which invalidates every entry
+const CACHE_VERSION: u32 = 1;
+const MAGIC: &[u8; 4] = b"DICE";
+
+#[derive(Resource, Clone)]
+pub struct Cache {
+    dir: Option<PathBuf>,
+}
+
+// fnv-1a, because the std hasher is not guaranteed to be stable between rust releases
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct CacheKey(u64);
+
+struct Decoder<'a>(&'a [u8]);
+
+impl CacheKey {
+    pub fn new(kind: &str) -> Self {
+        CacheKey(0xcbf29ce484222325)
+            .bytes(&CACHE_VERSION.to_le_bytes())
+            .bytes(env!("CARGO_PKG_VERSION").as_bytes())
+            .bytes(kind.as_bytes())
+    }
+
+    // the length is hashed as well, so that ("ab", "c") and ("a", "bc") differ
+    pub fn bytes(mut self, bytes: &[u8]) -> Self {
+        for byte in (bytes.len() as u64).to_le_bytes().iter().chain(bytes) {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+        self
+    }
+
+    pub fn floats(self, floats: &[f32]) -> Self {
+        let bytes = floats
+            .iter()
+            .flat_map(|float| float.to_le_bytes())
+            .collect::<Vec<_>>();
+        self.bytes(&bytes)
+    }
+
+    // addresses a mesh by its content, for meshes we did not generate ourselves
+    pub fn mesh(self, mesh: &Mesh) -> Self {
+        let positions = mesh
+            .attribute(Mesh::ATTRIBUTE_POSITION)
+            .and_then(VertexAttributeValues::as_float3)
+            .unwrap_or_default();
+        let indices = mesh
+            .indices()
+            .map(|indices| {
+                indices
+                    .iter()
+                    .flat_map(|index| (index as u32).to_le_bytes())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        self.floats(positions.as_flattened()).bytes(&indices)
+    }
+
+    fn file_name(&self, extension: &str) -> String {
+        format!("{:016x}.{extension}", self.0)
+    }
+}
+
+impl Cache {
+    // DICE_CACHE overrides the directory, setting it to an empty value disables the cache
+    pub fn from_env() -> Self {
+        let dir = match std::env::var_os("DICE_CACHE") {
+            Some(dir) if dir.is_empty() => None,
+            Some(dir) => Some(PathBuf::from(dir)),
+            None => std::env::var_os("XDG_CACHE_HOME")
+                .map(PathBuf::from)
+                .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+                .map(|dir| dir.join("dice")),
+        };
+        Cache { dir }
+    }
+
+    // everything is generated, for tests and benchmarks that must not depend on earlier runs
+    pub fn disabled() -> Self {
+        Cache { dir: None }
+    }
+
+    pub fn mesh<E>(
+        &self,
+        key: CacheKey,
+        generate: impl FnOnce() -> Result<Mesh, E>,
+    ) -> Result<Mesh, E> {
+        if let Some(mesh) = self.read(key, "mesh").and_then(|bytes| decode_mesh(&bytes)) {
+            return Ok(mesh);
+        }
+        let mesh = generate()?;
+        if let Some(bytes) = encode_mesh(&mesh) {
+            self.write(key, "mesh", bytes);
+        }
+        Ok(mesh)
+    }
+
+    pub fn collider<E>(
+        &self,
+        key: CacheKey,
+        generate: impl FnOnce() -> Result<Collider, E>,
+    ) -> Result<Collider, E> {
+        if let Some(collider) = self
+            .read(key, "collider")
+            .and_then(|bytes| decode_collider(&bytes))
+        {
+            return Ok(collider);
+        }
+        let collider = generate()?;
+        if let Some(bytes) = encode_collider(&collider) {
+            self.write(key, "collider", bytes);
+        }
+        Ok(collider)
+    }
+
+    // unreadable or outdated entries count as misses and are overwritten
+    fn read(&self, key: CacheKey, extension: &str) -> Option<Vec<u8>> {
+        let bytes = fs::read(self.dir.as_ref()?.join(key.file_name(extension))).ok()?;
+        let mut decoder = Decoder(bytes.strip_prefix(MAGIC)?);
+        if decoder.u32()? != CACHE_VERSION {
+            return None;
+        }
+        Some(decoder.0.to_vec())
+    }
+
+    fn write(&self, key: CacheKey, extension: &str, payload: Vec<u8>) {
+        let Some(dir) = &self.dir else {
+            return;
+        };
+        let path = dir.join(key.file_name(extension));
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(CACHE_VERSION.to_le_bytes());
+        bytes.extend(payload);
+        // written to a temporary file first, so that a crash never leaves half an entry behind
+        let temporary = path.with_extension("tmp");
+        let result = fs::create_dir_all(dir)
+            .and_then(|_| fs::write(&temporary, bytes))
+            .and_then(|_| fs::rename(&temporary, &path));
+        if let Err(error) = result {
+            warn!("could not write {path:?} to the cache: {error}");
+        }
+    }
+}
+
+impl Decoder<'_> {
+    fn u32(&mut self) -> Option<u32> {
+        let (bytes, rest) = self.0.split_first_chunk::<4>()?;
+        self.0 = rest;
+        Some(u32::from_le_bytes(*bytes))
+    }
+
+    fn u32s(&mut self) -> Option<Vec<u32>> {
+        let count = self.u32()? as usize;
+        let (bytes, rest) = self.0.split_at_checked(count.checked_mul(4)?)?;
+        self.0 = rest;
+        Some(
+            bytes
+                .chunks_exact(4)
+                .map(|chunk| u32::from_le_bytes(chunk.try_into().expect("chunk of four")))
+                .collect(),
+        )
+    }
+
+    fn floats(&mut self) -> Option<Vec<f32>> {
+        Some(self.u32s()?.into_iter().map(f32::from_bits).collect())
+    }
+}
+
+fn encode_u32s(bytes: &mut Vec<u8>, values: impl ExactSizeIterator<Item = u32>) {
+    bytes.extend((values.len() as u32).to_le_bytes());
+    bytes.extend(values.flat_map(u32::to_le_bytes));
+}
+
+fn encode_floats(bytes: &mut Vec<u8>, floats: &[f32]) {
+    encode_u32s(bytes, floats.iter().map(|float| float.to_bits()));
+}
+
+// missing attributes are stored as empty lists
+fn encode_mesh(mesh: &Mesh) -> Option<Vec<u8>> {
+    if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+        return None;
+    }
+    let mut bytes = vec![];
+    for attribute in [
+        Mesh::ATTRIBUTE_POSITION,
+        Mesh::ATTRIBUTE_NORMAL,
+        Mesh::ATTRIBUTE_UV_0,
+        Mesh::ATTRIBUTE_TANGENT,
+    ] {
+        let floats = match mesh.attribute(attribute) {
+            Some(VertexAttributeValues::Float32x2(values)) => values.as_flattened(),
+            Some(VertexAttributeValues::Float32x3(values)) => values.as_flattened(),
+            Some(VertexAttributeValues::Float32x4(values)) => values.as_flattened(),
+            Some(_) => return None,
+            None => &[],
+        };
+        encode_floats(&mut bytes, floats);
+    }
+    let indices = mesh
+        .indices()?
+        .iter()
+        .map(|index| index as u32)
+        .collect::<Vec<_>>();
+    encode_u32s(&mut bytes, indices.into_iter());
+    Some(bytes)
+}
+
+fn decode_mesh(bytes: &[u8]) -> Option<Mesh> {
+    let mut decoder = Decoder(bytes);
+    let positions = decoder.floats()?;
+    let normals = decoder.floats()?;
+    let uvs = decoder.floats()?;
+    let tangents = decoder.floats()?;
+    let indices = decoder.u32s()?;
+    let count = positions.len() / 3;
+    if positions.is_empty() || positions.len() % 3 != 0 || !decoder.0.is_empty() {
+        return None;
+    }
+    if indices.len() % 3 != 0 || indices.iter().any(|index| *index as usize >= count) {
+        return None;
+    }
+    let mut mesh = Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::default(),
+    );
+    mesh.insert_attribute(
+        Mesh::ATTRIBUTE_POSITION,
+        positions
+            .chunks_exact(3)
+            .map(|p| [p[0], p[1], p[2]])
+            .collect::<Vec<_>>(),
+    );
+    if normals.len() == count * 3 {
+        let normals = normals.chunks_exact(3).map(|n| [n[0], n[1], n[2]]);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals.collect::<Vec<_>>());
+    }
+    if uvs.len() == count * 2 {
+        let uvs = uvs.chunks_exact(2).map(|uv| [uv[0], uv[1]]);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs.collect::<Vec<_>>());
+    }
+    if tangents.len() == count * 4 {
+        let tangents = tangents.chunks_exact(4).map(|t| [t[0], t[1], t[2], t[3]]);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_TANGENT, tangents.collect::<Vec<_>>());
+    }
+    mesh.insert_indices(Indices::U32(indices));
+    Some(mesh)
+}
+
+// only colliders made of convex hulls are cached, which covers the vhacd decompositions;
+// the hull points are stored and the hulls are rebuilt, which is cheap compared to vhacd
+fn encode_collider(collider: &Collider) -> Option<Vec<u8>> {
+    let shape = collider.shape();
+    let parts = match shape.as_compound() {
+        Some(compound) => compound
+            .shapes()
+            .iter()
+            .map(|(isometry, shape)| (*isometry, shape.as_convex_polyhedron()))
+            .collect::<Vec<_>>(),
+        None => vec![(Isometry::identity(), shape.as_convex_polyhedron())],
+    };
+    let mut bytes = (parts.len() as u32).to_le_bytes().to_vec();
+    for (isometry, hull) in parts {
+        let translation = isometry.translation.vector;
+        let rotation = isometry.rotation;
+        encode_floats(
+            &mut bytes,
+            &[
+                translation.x,
+                translation.y,
+                translation.z,
+                rotation.i,
+                rotation.j,
+                rotation.k,
+                rotation.w,
+            ],
+        );
+        let points = hull?
+            .points()
+            .iter()
+            .flat_map(|point| [point.x, point.y, point.z])
+            .collect::<Vec<_>>();
+        encode_floats(&mut bytes, &points);
+    }
+    Some(bytes)
+}
+
+fn decode_collider(bytes: &[u8]) -> Option<Collider> {
+    let mut decoder = Decoder(bytes);
+    let count = decoder.u32()?;
+    let mut parts = vec![];
+    for _ in 0..count {
+        let isometry = decoder.floats()?;
+        let [x, y, z, i, j, k, w] = isometry[..] else {
+            return None;
+        };
+        let points = decoder.floats()?;
+        if points.len() % 3 != 0 {
+            return None;
+        }
+        let points = points
+            .chunks_exact(3)
+            .map(|p| Vec3::new(p[0], p[1], p[2]))
+            .collect();
+        parts.push((
+            Position::new(Vec3::new(x, y, z)),
+            Rotation::from(Quat::from_xyzw(i, j, k, w)),
+            Collider::convex_hull(points)?,
+        ));
+    }
+    if parts.is_empty() || !decoder.0.is_empty() {
+        return None;
+    }
+    Some(Collider::compound(parts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{create_d6, create_icosphere};
+
+    // the process id keeps test runs that happen at the same time apart
+    fn temporary_cache(name: &str) -> Cache {
+        let dir =
+            std::env::temp_dir().join(format!("dice-cache-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        Cache { dir: Some(dir) }
+    }
+
+    #[test]
+    fn keys_depend_on_every_parameter() {
+        let key = CacheKey::new("d6").floats(&[0.72, 0.6]);
+        assert_eq!(key, CacheKey::new("d6").floats(&[0.72, 0.6]));
+        assert_ne!(key, CacheKey::new("d6").floats(&[0.72, 0.61]));
+        assert_ne!(key, CacheKey::new("d8").floats(&[0.72, 0.6]));
+        assert_ne!(
+            CacheKey::new("a").bytes(b"bc"),
+            CacheKey::new("a").bytes(b"b").bytes(b"c")
+        );
+    }
+
+    #[test]
+    fn atlas_settings_are_part_of_the_key() {
+        use crate::atlas::{AtlasSettings, Marking};
+        let labels = ["1", "2", "3", "4", "5", "6"];
+        let atlas = AtlasSettings::default();
+        let key = atlas.key(CacheKey::new("engraved"), &labels);
+        assert_eq!(key, atlas.key(CacheKey::new("engraved"), &labels));
+        let other = [
+            AtlasSettings {
+                pip_radius: 0.1,
+                ..default()
+            },
+            AtlasSettings {
+                marking: Marking::Numerals,
+                ..default()
+            },
+            AtlasSettings {
+                resolution: 128,
+                ..default()
+            },
+        ];
+        for other in other {
+            assert_ne!(key, other.key(CacheKey::new("engraved"), &labels));
+        }
+        assert_ne!(
+            key,
+            atlas.key(CacheKey::new("engraved"), &["1", "2", "3", "4", "5", "X"])
+        );
+    }
+
+    #[test]
+    fn disabled_caches_always_generate() {
+        let cache = Cache::disabled();
+        let key = CacheKey::new("disabled");
+        let mut generated = 0;
+        for _ in 0..2 {
+            cache
+                .mesh(key, || {
+                    generated += 1;
+                    create_icosphere(0)
+                })
+                .unwrap();
+        }
+        assert_eq!(generated, 2);
+    }
+
+    #[test]
+    fn meshes_survive_a_round_trip() {
+        let cache = temporary_cache("mesh");
+        let key = CacheKey::new("d6");
+        let generated = cache
+            .mesh(key, || create_d6(3, 0.72, 0.6, [2, 5, 6, 1, 4, 3]))
+            .unwrap();
+        let cached = cache
+            .mesh(key, || -> Result<Mesh, ()> { panic!("cache miss") })
+            .unwrap();
+        for attribute in [
+            Mesh::ATTRIBUTE_POSITION,
+            Mesh::ATTRIBUTE_NORMAL,
+            Mesh::ATTRIBUTE_UV_0,
+            Mesh::ATTRIBUTE_TANGENT,
+        ] {
+            assert_eq!(
+                generated
+                    .attribute(attribute)
+                    .map(|values| values.get_bytes()),
+                cached.attribute(attribute).map(|values| values.get_bytes())
+            );
+        }
+        let indices = |mesh: &Mesh| mesh.indices().unwrap().iter().collect::<Vec<_>>();
+        assert_eq!(indices(&generated), indices(&cached));
+    }
+
+    #[test]
+    fn colliders_survive_a_round_trip() {
+        let cache = temporary_cache("collider");
+        let mesh = create_icosphere(2).unwrap();
+        let key = CacheKey::new("collider").mesh(&mesh);
+        let generated = cache
+            .collider(key, || Collider::convex_hull_from_mesh(&mesh).ok_or(()))
+            .unwrap();
+        let cached = cache
+            .collider(key, || -> Result<Collider, ()> { panic!("cache miss") })
+            .unwrap();
+        let generated = generated.aabb(Vec3::ZERO, Quat::IDENTITY);
+        let cached = cached.aabb(Vec3::ZERO, Quat::IDENTITY);
+        assert!(generated.min.abs_diff_eq(cached.min, 1e-6));
+        assert!(generated.max.abs_diff_eq(cached.max, 1e-6));
+    }
+
+    #[test]
+    fn corrupt_entries_are_regenerated() {
+        let cache = temporary_cache("corrupt");
+        let key = CacheKey::new("corrupt");
+        let dir = cache.dir.clone().unwrap();
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(key.file_name("mesh")), b"DICE\x01\0\0\0garbage").unwrap();
+        let mesh = cache.mesh(key, || create_icosphere(0)).unwrap();
+        assert_eq!(mesh.count_vertices(), 12);
+        assert!(
+            cache
+                .read(key, "mesh")
+                .and_then(|bytes| decode_mesh(&bytes))
+                .is_some()
+        );
+    }
+}
@@ -0,0 +1,398 @@
+use crate::atlas::AtlasSettings;
+use crate::cache::{Cache, CacheKey};
+use crate::geometry::{GeometryError, create_d6, create_rounded_d6, engrave};
+use crate::layout::FaceLayout;
+use avian3d::prelude::*;
+use bevy::prelude::*;
+
+pub const D6_SIZE: f32 = 0.6;
+pub const D6_DETAIL: u8 = 4;
+// detail of every level of detail and the camera distance from which it is shown; the camera
+// sees the near rim of the table from about 10 and the far rim from about 20
+pub const D6_LODS: [(u8, f32); 3] = [(D6_DETAIL, 0.0), (3, 13.0), (2, 17.0)];
+// the collider only needs the silhouette, which the coarsest mesh already has
+const D6_COLLIDER_DETAIL: u8 = 2;
+// dice fall faster than the rest of the world, which makes rolls snappier
+pub const GRAVITY_SCALE: f32 = 20.0;
+// engravings are this fraction of the size deep, which is still printable at common die sizes
+const ENGRAVING_DEPTH: f32 = 0.04;
+// edges are split down to this length, so that a pip is spanned by about a dozen vertices
+const ENGRAVING_EDGE: f32 = D6_SIZE / 48.0;
+
+#[derive(Component)]
+pub struct Die;
+
+// the center of mass is moved away from `face`, so that it tends to end up on top
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Loaded {
+    pub face: u8,
+    pub bias: f32,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DieShape {
+    // a unit sphere cut by the faces at threshold, which rounds edges and corners alike
+    Spherical {
+        threshold: f32,
+    },
+    // radii are fractions of the size, both at zero give the sharp edges of casino dice
+    Rounded {
+        edge_radius: f32,
+        corner_radius: f32,
+    },
+}
+
+#[derive(Resource)]
+pub struct SelectedShape(pub DieShape);
+
+// carves the markings into the mesh instead of faking them with parallax mapping
+#[derive(Resource, Clone, Copy, PartialEq, Debug, Default)]
+pub struct Engraved(pub bool);
+
+// every d6 shape is convex, so a single hull is both exact and the cheapest mesh based collider
+#[derive(Resource, Clone, Copy, PartialEq, Debug, Default)]
+pub enum ColliderMode {
+    #[default]
+    ConvexHull,
+    RoundCuboid,
+    Decomposition,
+}
+
+pub struct DieBuilder {
+    transform: Transform,
+    angular_velocity: Vec3,
+    layout: FaceLayout,
+    loaded: Option<Loaded>,
+}
+
+impl Die {
+    pub fn builder() -> DieBuilder {
+        DieBuilder {
+            transform: Transform::default(),
+            angular_velocity: Vec3::ZERO,
+            layout: FaceLayout::default(),
+            loaded: None,
+        }
+    }
+}
+
+impl DieBuilder {
+    pub fn transform(mut self, transform: Transform) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    pub fn angular_velocity(mut self, angular_velocity: Vec3) -> Self {
+        self.angular_velocity = angular_velocity;
+        self
+    }
+
+    // has to match the layout the mesh was created with
+    pub fn layout(mut self, layout: FaceLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    // bias is the fraction of the half extent the center of mass is moved by
+    pub fn loaded(mut self, face: u8, bias: f32) -> Result<Self, GeometryError> {
+        if !(1..=6).contains(&face) {
+            return Err(GeometryError::InvalidParameters(format!(
+                "the loaded face must be between 1 and 6, got {face}"
+            )));
+        }
+        self.loaded = Some(Loaded {
+            face,
+            bias: bias.clamp(0.0, 1.0),
+        });
+        Ok(self)
+    }
+
+    pub fn spawn<'a>(self, commands: &'a mut Commands, collider: Collider) -> EntityCommands<'a> {
+        let mut entity = commands.spawn((
+            Die,
+            RigidBody::Dynamic,
+            GravityScale(GRAVITY_SCALE),
+            Restitution::new(0.4),
+            AngularVelocity(self.angular_velocity),
+            collider,
+            self.transform,
+            self.layout,
+        ));
+        if let Some(loaded) = self.loaded
+            && let Some(center_of_mass) = loaded.center_of_mass(&self.layout)
+        {
+            entity.insert((loaded, CenterOfMass(center_of_mass)));
+        }
+        entity
+    }
+}
+
+impl Loaded {
+    // <face>:<bias>, as given to --loaded
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let (face, bias) = value
+            .split_once(':')
+            .ok_or_else(|| format!("expected <face>:<bias>, got {value}"))?;
+        let face: u8 = face
+            .trim()
+            .parse()
+            .map_err(|_| format!("the loaded face must be a number, got {face}"))?;
+        let bias: f32 = bias
+            .trim()
+            .parse()
+            .map_err(|_| format!("the bias must be a number, got {bias}"))?;
+        if !(1..=6).contains(&face) || !(0.0..=1.0).contains(&bias) {
+            return Err(format!(
+                "expected a face between 1 and 6 and a bias between 0 and 1, got {value}"
+            ));
+        }
+        Ok(Loaded { face, bias })
+    }
+
+    // none for a face the layout does not have
+    pub fn center_of_mass(&self, layout: &FaceLayout) -> Option<Vec3> {
+        let normal = layout.normal(self.face)?;
+        Some(-normal * self.bias * D6_SIZE / 2.0)
+    }
+}
+
+impl ColliderMode {
+    pub const ALL: [ColliderMode; 3] = [
+        ColliderMode::ConvexHull,
+        ColliderMode::RoundCuboid,
+        ColliderMode::Decomposition,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColliderMode::ConvexHull => "hull",
+            ColliderMode::RoundCuboid => "round",
+            ColliderMode::Decomposition => "decomposition",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        ColliderMode::ALL
+            .into_iter()
+            .find(|mode| mode.name() == value)
+            .ok_or_else(|| {
+                format!("unknown collider {value}, expected hull, round or decomposition")
+            })
+    }
+}
+
+impl DieShape {
+    pub const CLASSIC: DieShape = DieShape::Spherical { threshold: 0.72 };
+    pub const ROUNDED: DieShape = DieShape::Rounded {
+        edge_radius: 0.08,
+        corner_radius: 0.16,
+    };
+    pub const CASINO: DieShape = DieShape::Rounded {
+        edge_radius: 0.0,
+        corner_radius: 0.0,
+    };
+    pub const PRESETS: [DieShape; 3] = [DieShape::CLASSIC, DieShape::ROUNDED, DieShape::CASINO];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DieShape::Spherical { .. } => "Classic",
+            DieShape::Rounded {
+                edge_radius,
+                corner_radius,
+            } if *edge_radius == 0.0 && *corner_radius == 0.0 => "Casino",
+            DieShape::Rounded { .. } => "Rounded",
+        }
+    }
+
+    // classic, rounded, casino or <edge radius>,<corner radius>
+    pub fn parse(value: &str) -> Result<Self, String> {
+        if let Some(preset) = DieShape::PRESETS
+            .into_iter()
+            .find(|preset| preset.name().eq_ignore_ascii_case(value))
+        {
+            return Ok(preset);
+        }
+        let (edge_radius, corner_radius) = value
+            .split_once(',')
+            .ok_or_else(|| format!("unknown shape {value}"))?;
+        let edge_radius: f32 = edge_radius
+            .trim()
+            .parse()
+            .map_err(|_| "edge radius must be a number")?;
+        let corner_radius: f32 = corner_radius
+            .trim()
+            .parse()
+            .map_err(|_| "corner radius must be a number")?;
+        // the bounds of create_rounded_d6 in fractions of the size, checked before any die is built
+        if !(0.0..=corner_radius).contains(&edge_radius) || corner_radius > 0.5 {
+            return Err(format!(
+                "radii must satisfy 0 <= edge <= corner <= 0.5, got {edge_radius} and {corner_radius}"
+            ));
+        }
+        Ok(DieShape::Rounded {
+            edge_radius,
+            corner_radius,
+        })
+    }
+
+    // also identifies the loaded shapes, as it covers all parameters
+    pub fn key(&self) -> CacheKey {
+        match *self {
+            DieShape::Spherical { threshold } => CacheKey::new("spherical d6").floats(&[threshold]),
+            DieShape::Rounded {
+                edge_radius,
+                corner_radius,
+            } => CacheKey::new("rounded d6").floats(&[edge_radius, corner_radius]),
+        }
+    }
+
+    // detail is the subdivision depth of the sphere or the doubling count of rounding segments
+    pub fn mesh(&self, layout: &FaceLayout, detail: u8) -> Result<Mesh, GeometryError> {
+        match *self {
+            DieShape::Spherical { threshold } => {
+                create_d6(detail, threshold, D6_SIZE, layout.values())
+            }
+            DieShape::Rounded {
+                edge_radius,
+                corner_radius,
+            } => create_rounded_d6(
+                1 << (detail.clamp(1, 6) - 1),
+                (edge_radius * D6_SIZE, corner_radius * D6_SIZE),
+                D6_SIZE,
+                layout.values(),
+            ),
+        }
+    }
+
+    // the labels are placed by the uvs of the mesh, just like the atlas textures are
+    pub fn engraved_mesh(
+        &self,
+        layout: &FaceLayout,
+        atlas: &AtlasSettings,
+        labels: &[&str],
+    ) -> Result<Mesh, GeometryError> {
+        let mesh = self.mesh(layout, D6_DETAIL)?;
+        engrave(&mesh, ENGRAVING_EDGE, |uv| {
+            atlas.depth_at(labels, uv) * ENGRAVING_DEPTH * D6_SIZE
+        })
+    }
+
+    // the border of a round cuboid approximating the shape
+    fn border(&self) -> f32 {
+        match *self {
+            DieShape::Spherical { threshold } => {
+                // the die is a cube cut by a sphere, the border is chosen so that the corners match
+                let half = D6_SIZE / 2.0;
+                let radius = half / threshold;
+                (3.0f32.sqrt() * half - radius) / (3.0f32.sqrt() - 1.0)
+            }
+            DieShape::Rounded { edge_radius, .. } => edge_radius * D6_SIZE,
+        }
+    }
+}
+
+impl Default for DieShape {
+    fn default() -> Self {
+        DieShape::CLASSIC
+    }
+}
+
+// returns the render meshes in the order of D6_LODS and the collider;
+// meshes are keyed by their generator parameters, the collider by the mesh it is built from
+pub fn cached_d6(
+    cache: &Cache,
+    layout: &FaceLayout,
+    shape: &DieShape,
+    mode: ColliderMode,
+) -> Result<(Vec<Mesh>, Collider), GeometryError> {
+    let mesh = |detail: u8| {
+        let key = shape
+            .key()
+            .bytes(&[detail])
+            .floats(&[D6_SIZE])
+            .bytes(&layout.values());
+        cache.mesh(key, || shape.mesh(layout, detail))
+    };
+    let meshes = D6_LODS
+        .iter()
+        .map(|(detail, _)| mesh(*detail))
+        .collect::<Result<Vec<_>, _>>()?;
+    let coarse = mesh(D6_COLLIDER_DETAIL)?;
+    let key = CacheKey::new("d6 collider")
+        .bytes(mode.name().as_bytes())
+        .floats(&[shape.border()])
+        .mesh(&coarse);
+    let collider = cache.collider(key, || d6_collider(&coarse, shape, mode))?;
+    Ok((meshes, collider))
+}
+
+// only the most detailed mesh is engraved, the markings are too small to be seen on the others
+pub fn cached_engraved_d6(
+    cache: &Cache,
+    layout: &FaceLayout,
+    shape: &DieShape,
+    atlas: &AtlasSettings,
+    labels: &[&str],
+) -> Result<Mesh, GeometryError> {
+    let key = shape
+        .key()
+        .floats(&[D6_SIZE, ENGRAVING_DEPTH, ENGRAVING_EDGE])
+        .bytes(&layout.values());
+    let key = atlas.key(key, labels);
+    cache.mesh(key, || shape.engraved_mesh(layout, atlas, labels))
+}
+
+pub fn d6_collider(
+    mesh: &Mesh,
+    shape: &DieShape,
+    mode: ColliderMode,
+) -> Result<Collider, GeometryError> {
+    match mode {
+        ColliderMode::ConvexHull => {
+            Collider::convex_hull_from_mesh(mesh).ok_or(GeometryError::ColliderGeneration)
+        }
+        ColliderMode::RoundCuboid => {
+            let border = shape.border();
+            let length = D6_SIZE - 2.0 * border;
+            Ok(Collider::round_cuboid(length, length, length, border))
+        }
+        ColliderMode::Decomposition => Collider::convex_decomposition_from_mesh_with_config(
+            mesh,
+            &VhacdParameters {
+                fill_mode: FillMode::SurfaceOnly,
+                ..default()
+            },
+        )
+        .ok_or(GeometryError::ColliderGeneration),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_center_of_mass_is_moved_away_from_the_loaded_face() {
+        for layout in FaceLayout::PRESETS {
+            for face in 1..=6 {
+                let loaded = Loaded { face, bias: 0.5 };
+                let center_of_mass = loaded.center_of_mass(&layout).unwrap();
+                let normal = layout.normal(face).unwrap();
+                assert!(center_of_mass.dot(normal) < 0.0, "{} {face}", layout.name());
+                assert!((center_of_mass.length() - D6_SIZE / 4.0).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn only_faces_of_the_die_can_be_loaded() {
+        assert!(Die::builder().loaded(0, 0.5).is_err());
+        assert!(Die::builder().loaded(7, 0.5).is_err());
+        let builder = Die::builder().loaded(6, 2.0).unwrap();
+        assert_eq!(builder.loaded.unwrap().bias, 1.0);
+        assert!(Loaded::parse("6:0.5").is_ok());
+        for value in ["6", "0:0.5", "7:0.5", "6:1.5", "six:0.5", "6:much"] {
+            assert!(Loaded::parse(value).is_err(), "{value}");
+        }
+    }
+}
@@ -0,0 +1,464 @@
+use crate::atlas::{AtlasSettings, Marking};
+use crate::cli::{has_flag, value_of};
+use crate::die::{D6_DETAIL, DieShape};
+use crate::faces::DieFaces;
+use crate::layout::FaceLayout;
+use crate::validation::validate_mesh;
+use bevy::prelude::*;
+use bevy::render::mesh::VertexAttributeValues;
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+pub struct ExportConfig {
+    pub path: PathBuf,
+    pub layout: FaceLayout,
+    pub shape: DieShape,
+    pub engraved: bool,
+    pub marking: Marking,
+    pub scale: f32,
+}
+
+enum Format {
+    Glb,
+    Obj,
+    Stl,
+}
+
+struct MeshData {
+    positions: Vec<[f32; 3]>,
+    normals: Option<Vec<[f32; 3]>>,
+    uvs: Option<Vec<[f32; 2]>>,
+    tangents: Option<Vec<[f32; 4]>>,
+    indices: Vec<u32>,
+}
+
+impl ExportConfig {
+    // dice --export <path.glb|path.obj|path.stl> [--layout <layout>] [--shape <shape>]
+    //      [--engrave] [--marking <pips|numerals>] [--scale <factor>]
+    pub fn from_args(args: &[String]) -> Option<Self> {
+        let path = PathBuf::from(value_of(args, "--export")?);
+        let layout = value_of(args, "--layout")
+            .map(|value| FaceLayout::parse(value).expect("--layout"))
+            .unwrap_or_default();
+        let shape = value_of(args, "--shape")
+            .map(|value| DieShape::parse(value).expect("--shape"))
+            .unwrap_or_default();
+        let engraved = has_flag(args, "--engrave");
+        let marking = value_of(args, "--marking")
+            .map(|value| Marking::parse(value).expect("--marking"))
+            .unwrap_or_default();
+        let scale = value_of(args, "--scale")
+            .map(|value| value.parse().expect("--scale expects a number"))
+            .unwrap_or(1.0);
+        Some(ExportConfig {
+            path,
+            layout,
+            shape,
+            engraved,
+            marking,
+            scale,
+        })
+    }
+}
+
+pub fn run(config: ExportConfig) -> std::io::Result<()> {
+    // checked before anything is written, so that a wrong path does not leave an empty file behind
+    let format = Format::from_path(&config.path)?;
+    let mesh = if config.engraved {
+        let faces = DieFaces::numbered();
+        config.shape.engraved_mesh(
+            &config.layout,
+            &AtlasSettings {
+                marking: config.marking,
+                ..default()
+            },
+            &faces.texts(),
+        )
+    } else {
+        config.shape.mesh(&config.layout, D6_DETAIL)
+    }
+    .map_err(Error::other)?;
+    let report = validate_mesh(&mesh, 1e-6).map_err(Error::other)?;
+    if !report.is_valid() {
+        // slicers reject an stl that is not closed, the other formats are also used for viewing
+        if matches!(format, Format::Stl) {
+            return Err(Error::other(format!("the mesh is not printable: {report}")));
+        }
+        warn!("the exported mesh is not printable: {report}");
+    }
+    let mut file = BufWriter::new(File::create(&config.path)?);
+    format.write(&mesh, config.scale, &mut file)?;
+    file.flush()
+}
+
+impl Format {
+    fn from_path(path: &Path) -> std::io::Result<Self> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("glb") => Ok(Format::Glb),
+            Some("obj") => Ok(Format::Obj),
+            Some("stl") => Ok(Format::Stl),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "the export path has to end with .glb, .obj or .stl",
+            )),
+        }
+    }
+
+    fn write(&self, mesh: &Mesh, scale: f32, writer: &mut impl Write) -> std::io::Result<()> {
+        match self {
+            Format::Glb => write_glb(mesh, scale, writer),
+            Format::Obj => write_obj(mesh, scale, writer),
+            Format::Stl => write_stl(mesh, scale, writer),
+        }
+    }
+}
+
+impl MeshData {
+    fn extract(mesh: &Mesh, scale: f32) -> std::io::Result<Self> {
+        let invalid = |message| Error::new(ErrorKind::InvalidData, message);
+        let positions = mesh
+            .attribute(Mesh::ATTRIBUTE_POSITION)
+            .and_then(VertexAttributeValues::as_float3)
+            .ok_or_else(|| invalid("mesh has no positions"))?
+            .iter()
+            .map(|position| position.map(|c| c * scale))
+            .collect();
+        let normals = mesh
+            .attribute(Mesh::ATTRIBUTE_NORMAL)
+            .and_then(VertexAttributeValues::as_float3)
+            .map(Vec::from);
+        let uvs = match mesh.attribute(Mesh::ATTRIBUTE_UV_0) {
+            Some(VertexAttributeValues::Float32x2(uvs)) => Some(uvs.clone()),
+            _ => None,
+        };
+        let tangents = match mesh.attribute(Mesh::ATTRIBUTE_TANGENT) {
+            Some(VertexAttributeValues::Float32x4(tangents)) => Some(tangents.clone()),
+            _ => None,
+        };
+        let indices = mesh
+            .indices()
+            .ok_or_else(|| invalid("mesh has no indices"))?
+            .iter()
+            .map(|index| index as u32)
+            .collect::<Vec<_>>();
+        if indices.len() % 3 != 0 {
+            return Err(invalid("mesh is not a triangle list"));
+        }
+        Ok(MeshData {
+            positions,
+            normals,
+            uvs,
+            tangents,
+            indices,
+        })
+    }
+
+    fn triangles(&self) -> impl Iterator<Item = [Vec3; 3]> + '_ {
+        self.indices.chunks(3).map(|triangle| {
+            [0, 1, 2].map(|i| Vec3::from_array(self.positions[triangle[i] as usize]))
+        })
+    }
+}
+
+pub fn write_obj(mesh: &Mesh, scale: f32, writer: &mut impl Write) -> std::io::Result<()> {
+    let data = MeshData::extract(mesh, scale)?;
+    for [x, y, z] in &data.positions {
+        writeln!(writer, "v {x} {y} {z}")?;
+    }
+    // obj has its uv origin in the bottom left corner
+    for [u, v] in data.uvs.iter().flatten() {
+        writeln!(writer, "vt {u} {}", 1.0 - v)?;
+    }
+    for [x, y, z] in data.normals.iter().flatten() {
+        writeln!(writer, "vn {x} {y} {z}")?;
+    }
+    let vertex = |index: u32| {
+        let index = index + 1;
+        match (data.uvs.is_some(), data.normals.is_some()) {
+            (true, true) => format!("{index}/{index}/{index}"),
+            (true, false) => format!("{index}/{index}"),
+            (false, true) => format!("{index}//{index}"),
+            (false, false) => format!("{index}"),
+        }
+    };
+    for triangle in data.indices.chunks(3) {
+        writeln!(
+            writer,
+            "f {} {} {}",
+            vertex(triangle[0]),
+            vertex(triangle[1]),
+            vertex(triangle[2])
+        )?;
+    }
+    Ok(())
+}
+
+// binary stl, which only consists of triangles, so uvs, normals and tangents are lost
+pub fn write_stl(mesh: &Mesh, scale: f32, writer: &mut impl Write) -> std::io::Result<()> {
+    let data = MeshData::extract(mesh, scale)?;
+    writer.write_all(&[0; 80])?;
+    writer.write_all(&((data.indices.len() / 3) as u32).to_le_bytes())?;
+    for [a, b, c] in data.triangles() {
+        let normal = (b - a).cross(c - a).normalize_or_zero();
+        for vector in [normal, a, b, c] {
+            for component in vector.to_array() {
+                writer.write_all(&component.to_le_bytes())?;
+            }
+        }
+        writer.write_all(&0u16.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+pub fn write_glb(mesh: &Mesh, scale: f32, writer: &mut impl Write) -> std::io::Result<()> {
+    const ARRAY_BUFFER: u32 = 34962;
+    const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+    const FLOAT: u32 = 5126;
+    const UNSIGNED_INT: u32 = 5125;
+
+    let data = MeshData::extract(mesh, scale)?;
+    let mut buffer = vec![];
+    let mut buffer_views = vec![];
+    let mut accessors = vec![];
+    let mut attributes = vec![];
+    let mut add_accessor = |floats: Vec<f32>, kind: &str, count: usize, extra: String| {
+        let offset = buffer.len();
+        buffer.extend(floats.iter().flat_map(|f| f.to_le_bytes()));
+        buffer_views.push(format!(
+            r#"{{"buffer":0,"byteOffset":{offset},"byteLength":{},"target":{ARRAY_BUFFER}}}"#,
+            buffer.len() - offset
+        ));
+        accessors.push(format!(
+            r#"{{"bufferView":{},"componentType":{FLOAT},"count":{count},"type":"{kind}"{extra}}}"#,
+            buffer_views.len() - 1
+        ));
+        accessors.len() - 1
+    };
+
+    let count = data.positions.len();
+    let (min, max) = data.positions.iter().fold(
+        (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+        |(min, max), position| {
+            let position = Vec3::from_array(*position);
+            (min.min(position), max.max(position))
+        },
+    );
+    // gltf requires bounds for positions
+    let bounds = format!(
+        r#","min":[{},{},{}],"max":[{},{},{}]"#,
+        min.x, min.y, min.z, max.x, max.y, max.z
+    );
+    let positions = data.positions.iter().flatten().copied().collect();
+    attributes.push(format!(
+        r#""POSITION":{}"#,
+        add_accessor(positions, "VEC3", count, bounds)
+    ));
+    if let Some(normals) = &data.normals {
+        let normals = normals.iter().flatten().copied().collect();
+        let accessor = add_accessor(normals, "VEC3", count, String::new());
+        attributes.push(format!(r#""NORMAL":{accessor}"#));
+    }
+    if let Some(uvs) = &data.uvs {
+        let uvs = uvs.iter().flatten().copied().collect();
+        let accessor = add_accessor(uvs, "VEC2", count, String::new());
+        attributes.push(format!(r#""TEXCOORD_0":{accessor}"#));
+    }
+    if let Some(tangents) = &data.tangents {
+        let tangents = tangents.iter().flatten().copied().collect();
+        let accessor = add_accessor(tangents, "VEC4", count, String::new());
+        attributes.push(format!(r#""TANGENT":{accessor}"#));
+    }
+
+    let offset = buffer.len();
+    buffer.extend(data.indices.iter().flat_map(|index| index.to_le_bytes()));
+    buffer_views.push(format!(
+        r#"{{"buffer":0,"byteOffset":{offset},"byteLength":{},"target":{ELEMENT_ARRAY_BUFFER}}}"#,
+        buffer.len() - offset
+    ));
+    accessors.push(format!(
+        r#"{{"bufferView":{},"componentType":{UNSIGNED_INT},"count":{},"type":"SCALAR"}}"#,
+        buffer_views.len() - 1,
+        data.indices.len()
+    ));
+
+    let mut json = format!(
+        concat!(
+            r#"{{"asset":{{"version":"2.0","generator":"Dice"}},"#,
+            r#""scene":0,"scenes":[{{"nodes":[0]}}],"nodes":[{{"mesh":0,"name":"Die"}}],"#,
+            r#""meshes":[{{"primitives":[{{"attributes":{{{}}},"indices":{}}}]}}],"#,
+            r#""buffers":[{{"byteLength":{}}}],"bufferViews":[{}],"accessors":[{}]}}"#
+        ),
+        attributes.join(","),
+        accessors.len() - 1,
+        buffer.len(),
+        buffer_views.join(","),
+        accessors.join(",")
+    )
+    .into_bytes();
+    // chunks have to be aligned to four bytes
+    while json.len() % 4 != 0 {
+        json.push(b' ');
+    }
+    while buffer.len() % 4 != 0 {
+        buffer.push(0);
+    }
+
+    let length = 12 + 8 + json.len() + 8 + buffer.len();
+    writer.write_all(b"glTF")?;
+    writer.write_all(&2u32.to_le_bytes())?;
+    writer.write_all(&(length as u32).to_le_bytes())?;
+    writer.write_all(&(json.len() as u32).to_le_bytes())?;
+    writer.write_all(b"JSON")?;
+    writer.write_all(&json)?;
+    writer.write_all(&(buffer.len() as u32).to_le_bytes())?;
+    writer.write_all(b"BIN\0")?;
+    writer.write_all(&buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::create_d6;
+
+    fn mesh() -> Mesh {
+        create_d6(3, 0.72, 0.6, FaceLayout::default().values()).unwrap()
+    }
+
+    fn scaled(positions: &[[f32; 3]], scale: f32) -> Vec<[f32; 3]> {
+        positions
+            .iter()
+            .map(|position| position.map(|c| c * scale))
+            .collect()
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn f32_at(bytes: &[u8], offset: usize) -> f32 {
+        f32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn obj_survives_a_round_trip() {
+        let data = MeshData::extract(&mesh(), 1.0).unwrap();
+        let mut bytes = vec![];
+        write_obj(&mesh(), 2.0, &mut bytes).unwrap();
+        let (mut positions, mut uvs, mut normals, mut indices) = (vec![], vec![], vec![], vec![]);
+        for line in String::from_utf8(bytes).unwrap().lines() {
+            let (kind, rest) = line.split_once(' ').unwrap();
+            let floats = rest
+                .split(' ')
+                .filter_map(|f| f.parse::<f32>().ok())
+                .collect::<Vec<_>>();
+            match kind {
+                "v" => positions.push([floats[0], floats[1], floats[2]]),
+                "vt" => uvs.push([floats[0], floats[1]]),
+                "vn" => normals.push([floats[0], floats[1], floats[2]]),
+                "f" => {
+                    for corner in rest.split(' ') {
+                        let parts = corner
+                            .split('/')
+                            .map(|index| index.parse::<u32>().unwrap())
+                            .collect::<Vec<_>>();
+                        // the same index is used for the position, the uv and the normal
+                        assert!(parts.iter().all(|index| *index == parts[0]), "{line}");
+                        indices.push(parts[0] - 1);
+                    }
+                }
+                _ => panic!("unexpected line {line}"),
+            }
+        }
+        assert_eq!(positions, scaled(&data.positions, 2.0));
+        let flipped = data
+            .uvs
+            .unwrap()
+            .iter()
+            .map(|[u, v]| [*u, 1.0 - v])
+            .collect::<Vec<_>>();
+        assert_eq!(uvs, flipped);
+        assert_eq!(Some(normals), data.normals);
+        assert_eq!(indices, data.indices);
+    }
+
+    #[test]
+    fn stl_survives_a_round_trip() {
+        let data = MeshData::extract(&mesh(), 1.0).unwrap();
+        let mut bytes = vec![];
+        write_stl(&mesh(), 2.0, &mut bytes).unwrap();
+        let count = u32_at(&bytes, 80) as usize;
+        assert_eq!(count, data.indices.len() / 3);
+        assert_eq!(bytes.len(), 84 + count * 50);
+        let mut positions = vec![];
+        for (i, triangle) in data.indices.chunks(3).enumerate() {
+            let offset = 84 + i * 50;
+            let vector = |n: usize| {
+                Vec3::from_array([0, 1, 2].map(|c| f32_at(&bytes, offset + n * 12 + c * 4)))
+            };
+            // the facet normal points the same way as the normals of its corners
+            let corners = triangle
+                .iter()
+                .map(|index| Vec3::from_array(data.normals.as_ref().unwrap()[*index as usize]))
+                .sum::<Vec3>();
+            assert!(vector(0).dot(corners) > 0.0, "triangle {i}");
+            for (n, index) in (1..=3).zip(triangle) {
+                let expected = Vec3::from_array(data.positions[*index as usize]) * 2.0;
+                assert_eq!(vector(n), expected);
+                positions.push(vector(n).to_array());
+            }
+        }
+        let mut stl = Mesh::new(
+            bevy::render::mesh::PrimitiveTopology::TriangleList,
+            bevy::asset::RenderAssetUsages::default(),
+        );
+        stl.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        stl.insert_indices(bevy::render::mesh::Indices::U32(
+            (0..count as u32 * 3).collect(),
+        ));
+        let report = validate_mesh(&stl, 1e-5).unwrap();
+        assert!(report.is_watertight(), "{report}");
+    }
+
+    #[test]
+    fn glb_survives_a_round_trip() {
+        let data = MeshData::extract(&mesh(), 1.0).unwrap();
+        let mut bytes = vec![];
+        write_glb(&mesh(), 2.0, &mut bytes).unwrap();
+        let glb = gltf::Gltf::from_slice(&bytes).unwrap();
+        let blob = glb.blob.as_deref().unwrap();
+        let primitive = glb.meshes().next().unwrap().primitives().next().unwrap();
+        let reader = primitive.reader(|buffer| (buffer.index() == 0).then_some(blob));
+        let positions = reader.read_positions().unwrap().collect::<Vec<_>>();
+        assert_eq!(positions, scaled(&data.positions, 2.0));
+        assert_eq!(reader.read_normals().map(Iterator::collect), data.normals);
+        let uvs = reader
+            .read_tex_coords(0)
+            .map(|uvs| uvs.into_f32().collect());
+        assert_eq!(uvs, data.uvs);
+        assert!(data.tangents.is_some());
+        assert_eq!(reader.read_tangents().map(Iterator::collect), data.tangents);
+        let indices = reader
+            .read_indices()
+            .unwrap()
+            .into_u32()
+            .collect::<Vec<_>>();
+        assert_eq!(indices, data.indices);
+    }
+
+    #[test]
+    fn unknown_formats_are_rejected_before_writing() {
+        let dir = std::env::temp_dir().join(format!("dice-export-{}", std::process::id()));
+        let path = dir.join("die.fbx");
+        let config = ExportConfig {
+            path: path.clone(),
+            layout: FaceLayout::default(),
+            shape: DieShape::default(),
+            engraved: false,
+            marking: Marking::default(),
+            scale: 1.0,
+        };
+        assert!(run(config).is_err());
+        assert!(!path.exists());
+    }
+}
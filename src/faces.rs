@@ -0,0 +1,257 @@
+use bevy::prelude::*;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct FaceLabel {
+    pub text: String,
+    // faces without a value can only be listed, not summed up
+    pub value: Option<i32>,
+}
+
+// labels are indexed by face value - 1, which is also the cell in the atlas
+#[derive(Component, Clone, PartialEq, Debug)]
+pub struct DieFaces {
+    pub name: String,
+    pub labels: [FaceLabel; 6],
+}
+
+#[derive(Resource)]
+pub struct SelectedFaces(pub DieFaces);
+
+impl FaceLabel {
+    pub fn number(value: i32) -> Self {
+        FaceLabel {
+            text: value.to_string(),
+            value: Some(value),
+        }
+    }
+
+    pub fn symbol(text: &str, value: Option<i32>) -> Self {
+        FaceLabel {
+            text: String::from(text),
+            value,
+        }
+    }
+
+    // the blank faces of FATE dice are shown as an empty face
+    pub fn display(&self) -> &str {
+        if self.text.is_empty() {
+            "[ ]"
+        } else {
+            &self.text
+        }
+    }
+
+    fn is_number(&self) -> bool {
+        self.value
+            .is_some_and(|value| value.to_string() == self.text)
+    }
+}
+
+impl DieFaces {
+    pub fn custom(name: &str, labels: [FaceLabel; 6]) -> Self {
+        DieFaces {
+            name: String::from(name),
+            labels,
+        }
+    }
+
+    pub fn numbered() -> Self {
+        DieFaces::custom(
+            "Numbered",
+            std::array::from_fn(|i| FaceLabel::number(i as i32 + 1)),
+        )
+    }
+
+    pub fn fate() -> Self {
+        let (plus, minus, blank) = (
+            FaceLabel::symbol("+", Some(1)),
+            FaceLabel::symbol("−", Some(-1)),
+            FaceLabel::symbol("", Some(0)),
+        );
+        DieFaces::custom(
+            "FATE",
+            [
+                plus.clone(),
+                plus,
+                blank.clone(),
+                blank,
+                minus.clone(),
+                minus,
+            ],
+        )
+    }
+
+    pub fn directions() -> Self {
+        DieFaces::words("Directions", ["N", "E", "UP", "DOWN", "W", "S"])
+    }
+
+    pub fn colors() -> Self {
+        DieFaces::words("Colors", ["RED", "BLUE", "GREEN", "PINK", "GRAY", "GOLD"])
+    }
+
+    pub fn story() -> Self {
+        DieFaces::words("Story", ["KEY", "TREE", "MOON", "SHIP", "EYE", "STAR"])
+    }
+
+    pub fn words(name: &str, words: [&str; 6]) -> Self {
+        DieFaces::custom(name, words.map(|word| FaceLabel::symbol(word, None)))
+    }
+
+    pub fn presets() -> Vec<DieFaces> {
+        vec![
+            DieFaces::numbered(),
+            DieFaces::fate(),
+            DieFaces::directions(),
+            DieFaces::colors(),
+            DieFaces::story(),
+        ]
+    }
+
+    // a preset or six comma separated labels for the faces 1 to 6, where numbers are summed up
+    pub fn parse(value: &str) -> Result<Self, String> {
+        if let Some(preset) = DieFaces::presets()
+            .into_iter()
+            .find(|preset| preset.name.eq_ignore_ascii_case(value))
+        {
+            return Ok(preset);
+        }
+        if value.contains('\n') {
+            return Err(String::from("faces need a single line of text"));
+        }
+        let labels = value
+            .split(',')
+            .map(str::trim)
+            .map(|label| match label.parse() {
+                Ok(value) => FaceLabel::number(value),
+                Err(_) => FaceLabel::symbol(label, None),
+            })
+            .collect::<Vec<_>>();
+        let labels = <[FaceLabel; 6]>::try_from(labels)
+            .map_err(|_| String::from("custom faces need exactly six labels"))?;
+        // the labels make up the name, which tells the atlases apart and is sent to clients
+        let name = labels
+            .iter()
+            .map(|label| label.text.as_str())
+            .collect::<Vec<_>>()
+            .join(",");
+        Ok(DieFaces::custom(&name, labels))
+    }
+
+    pub fn label(&self, face: u8) -> &FaceLabel {
+        &self.labels[face as usize - 1]
+    }
+
+    pub fn texts(&self) -> Vec<&str> {
+        self.labels
+            .iter()
+            .map(|label| label.text.as_str())
+            .collect()
+    }
+}
+
+// the title is the roll or the player it belongs to
+pub fn describe(title: &str, results: &[FaceLabel]) -> String {
+    if results.is_empty() {
+        return format!("{title}:");
+    }
+    let values = results
+        .iter()
+        .map(|result| result.value)
+        .collect::<Option<Vec<_>>>();
+    let Some(values) = values else {
+        let labels = results.iter().map(FaceLabel::display).collect::<Vec<_>>();
+        return format!("{title}: {}", labels.join(", "));
+    };
+    let total = values.iter().sum::<i32>();
+    if results.iter().all(FaceLabel::is_number) {
+        let labels = results.iter().map(FaceLabel::display).collect::<Vec<_>>();
+        if results.len() == 1 {
+            return format!("{title}: {}", labels[0]);
+        }
+        return format!("{title}: {} = {total}", labels.join(" + "));
+    }
+    let labels = results.iter().map(FaceLabel::display).collect::<Vec<_>>();
+    format!("{title}: {} = {total:+}", labels.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn custom_faces_are_parsed_from_their_labels() {
+        assert_eq!(DieFaces::parse("fate"), Ok(DieFaces::fate()));
+        let faces = DieFaces::parse("A, b,3 ,SKULL,5,6").unwrap();
+        assert_eq!(faces.name, "A,b,3,SKULL,5,6");
+        assert_eq!(faces.label(3), &FaceLabel::number(3));
+        assert_eq!(faces.label(4), &FaceLabel::symbol("SKULL", None));
+        // the name is enough to build the same die again
+        assert_eq!(DieFaces::parse(&faces.name), Ok(faces));
+        assert!(DieFaces::parse("1,2,3").is_err());
+        assert!(DieFaces::parse("1,2,3,4,5,6,7").is_err());
+    }
+
+    #[test]
+    fn fate_rolls_are_summed_up() {
+        let fate = DieFaces::fate();
+        let (plus, blank, minus) = (fate.label(1), fate.label(3), fate.label(5));
+        assert_eq!(minus.text, "−");
+        assert_eq!(
+            describe("Roll", &[plus.clone(), plus.clone(), minus.clone()]),
+            "Roll: + + − = +1"
+        );
+        assert_eq!(
+            describe("Roll", &[plus.clone(), blank.clone(), minus.clone()]),
+            "Roll: + [ ] − = +0"
+        );
+        assert_eq!(
+            describe("Roll", &[blank.clone(), minus.clone()]),
+            "Roll: [ ] − = -1"
+        );
+    }
+
+    #[test]
+    fn words_are_listed() {
+        let story = DieFaces::story();
+        assert_eq!(
+            describe("Ann", &[story.label(1).clone(), story.label(6).clone()]),
+            "Ann: KEY, STAR"
+        );
+    }
+
+    #[test]
+    fn numbers_are_added() {
+        let numbered = DieFaces::numbered();
+        assert_eq!(describe("Roll", &[]), "Roll:");
+        assert_eq!(describe("Roll", &[numbered.label(4).clone()]), "Roll: 4");
+        assert_eq!(
+            describe(
+                "Roll",
+                &[numbered.label(4).clone(), numbered.label(6).clone()]
+            ),
+            "Roll: 4 + 6 = 10"
+        );
+    }
+
+    #[test]
+    fn mixed_and_concealed_rolls_are_listed() {
+        let numbered = DieFaces::numbered();
+        let concealed = FaceLabel::symbol("?", None);
+        // a concealed die has no value, so the visible ones are not summed up
+        assert_eq!(
+            describe("Roll", &[numbered.label(2).clone(), concealed.clone()]),
+            "Roll: 2, ?"
+        );
+        let word = DieFaces::directions().label(1).clone();
+        assert_eq!(
+            describe("Roll", &[numbered.label(2).clone(), word]),
+            "Roll: 2, N"
+        );
+        // symbols with a value are summed up along with the numbers
+        let fate = DieFaces::fate();
+        assert_eq!(
+            describe("Roll", &[numbered.label(2).clone(), fate.label(5).clone()]),
+            "Roll: 2 − = +1"
+        );
+    }
+}
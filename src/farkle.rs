@@ -0,0 +1,572 @@
+use crate::die::Die;
+use crate::faces::DieFaces;
+use crate::game::{GameMode, Seats};
+use crate::players::Players;
+use crate::{CountDie, Counted, DieSpawner, Roll};
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{EguiContextPass, EguiContexts, egui};
+use rand::Rng;
+use std::f32::consts::TAU;
+
+// farkle, or zehntausend: scoring dice are set aside and the rest rolled again, until banking or busting
+pub struct FarklePlugin;
+
+const DICE: usize = 6;
+
+// the rules that differ from table to table
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct HouseRules {
+    // the first to bank this many points wins
+    pub target: i32,
+    // the points a turn needs before they can be banked
+    pub minimum: i32,
+    pub straight: i32,
+    // 0 when three pairs do not score
+    pub three_pairs: i32,
+    // every die beyond a triple doubles it, instead of adding the triple again
+    pub doubling: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Phase {
+    Rolling,
+    Settling,
+    // the player holds scoring dice and keeps rolling or banks
+    Choosing,
+    // a throw without a single scoring die loses the points of the turn
+    Busted,
+    Over { winner: usize },
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Action {
+    Roll,
+    Hold(Entity),
+    KeepRolling,
+    Bank,
+    NextPlayer,
+    NewGame,
+}
+
+#[derive(Resource)]
+pub struct Farkle {
+    phase: Phase,
+    rules: HouseRules,
+    // the points of the dice set aside this turn
+    turn: i32,
+    // how many dice were set aside, the rest are rolled
+    set_aside: usize,
+    held: Vec<Entity>,
+    // clicked in the panel, carried out by play_farkle
+    action: Option<Action>,
+    // the dice left to roll are thrown onto the table
+    deal: bool,
+    // the banked points of this game by seat, the session score only counts won games
+    scores: Vec<i32>,
+    // the game starts over when somebody joins, leaves or moves, as the scores are kept by seat
+    seats: Seats,
+}
+
+impl Plugin for FarklePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                start_farkle.run_if(resource_changed::<GameMode>),
+                (play_farkle, throw_dice).run_if(resource_exists::<Farkle>),
+            )
+                .chain(),
+        )
+        .add_systems(
+            EguiContextPass,
+            farkle_panel.run_if(resource_exists::<Farkle>),
+        );
+    }
+}
+
+impl Default for HouseRules {
+    fn default() -> Self {
+        HouseRules {
+            target: 10000,
+            minimum: 350,
+            straight: 1500,
+            three_pairs: 750,
+            doubling: true,
+        }
+    }
+}
+
+fn counts(values: &[u8]) -> [i32; 7] {
+    let mut counts = [0; 7];
+    for value in values {
+        counts[*value as usize] += 1;
+    }
+    counts
+}
+
+impl HouseRules {
+    // the points of dice that are set aside together, none if any of them does not score
+    pub fn score(&self, values: &[u8]) -> Option<i32> {
+        if values.is_empty() || values.iter().any(|value| !(1..=6).contains(value)) {
+            return None;
+        }
+        let counts = counts(values);
+        if values.len() == DICE && counts[1..].iter().all(|count| *count == 1) {
+            return Some(self.straight);
+        }
+        if self.three_pairs > 0 && counts.iter().filter(|count| **count == 2).count() == 3 {
+            return Some(self.three_pairs);
+        }
+        let mut score = 0;
+        for (face, count) in counts.into_iter().enumerate().skip(1) {
+            let face = face as i32;
+            score += match (face, count) {
+                (_, 0) => 0,
+                (_, 3..) => {
+                    let triple = if face == 1 { 1000 } else { face * 100 };
+                    if self.doubling {
+                        triple << (count - 3)
+                    } else {
+                        triple * (count - 2)
+                    }
+                }
+                (1, count) => 100 * count,
+                (5, count) => 50 * count,
+                _ => return None,
+            };
+        }
+        Some(score)
+    }
+
+    // a throw busts unless a one, a five, a triple or a combination of all dice is among it
+    pub fn scores_any(&self, values: &[u8]) -> bool {
+        let counts = counts(values);
+        counts[1] > 0
+            || counts[5] > 0
+            || counts.iter().any(|count| *count >= 3)
+            || self.score(values).is_some()
+    }
+}
+
+impl Farkle {
+    fn new(rules: HouseRules, players: &Players) -> Self {
+        Farkle {
+            phase: Phase::Rolling,
+            rules,
+            turn: 0,
+            set_aside: 0,
+            held: vec![],
+            action: None,
+            deal: false,
+            scores: vec![0; players.list.len()],
+            seats: Seats::new(players),
+        }
+    }
+
+    // held are the points of the held dice, true if the dice are thrown and have to be counted
+    fn act(&mut self, action: Action, held: i32, players: &mut Players) -> bool {
+        match (self.phase, action) {
+            (Phase::Rolling, Action::Roll) => {
+                self.deal = true;
+                self.phase = Phase::Settling;
+                return true;
+            }
+            (Phase::Choosing, Action::Hold(entity)) => {
+                if let Some(i) = self.held.iter().position(|held| *held == entity) {
+                    self.held.remove(i);
+                } else {
+                    self.held.push(entity);
+                }
+            }
+            // when all six dice are set aside they are hot, and all of them are rolled again
+            (Phase::Choosing, Action::KeepRolling) if held > 0 => {
+                self.turn += held;
+                self.set_aside = (self.set_aside + self.held.len()) % DICE;
+                self.held.clear();
+                self.deal = true;
+                self.phase = Phase::Settling;
+                return true;
+            }
+            (Phase::Choosing, Action::Bank)
+                if held > 0 && self.turn + held >= self.rules.minimum =>
+            {
+                let active = players.active;
+                self.scores[active] += self.turn + held;
+                if self.scores[active] >= self.rules.target {
+                    players.list[active].score += 1;
+                    self.phase = Phase::Over { winner: active };
+                } else {
+                    players.next();
+                    self.next_turn();
+                }
+            }
+            (Phase::Busted, Action::NextPlayer) => {
+                players.next();
+                self.next_turn();
+            }
+            (Phase::Over { .. }, Action::NewGame) => *self = Farkle::new(self.rules, players),
+            _ => {}
+        }
+        false
+    }
+
+    fn next_turn(&mut self) {
+        self.phase = Phase::Rolling;
+        self.turn = 0;
+        self.set_aside = 0;
+        self.held.clear();
+    }
+}
+
+fn start_farkle(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    players: Res<Players>,
+    game: Option<Res<Farkle>>,
+) {
+    if *mode == GameMode::Farkle {
+        commands.insert_resource(Farkle::new(HouseRules::default(), &players));
+    } else if game.is_some() {
+        commands.remove_resource::<Farkle>();
+    }
+}
+
+fn play_farkle(
+    mut game: ResMut<Farkle>,
+    mut players: ResMut<Players>,
+    mut count_die: ResMut<CountDie>,
+    roll: Single<&Roll>,
+    dice: Query<Has<Counted>, With<Die>>,
+) {
+    let values = |held: Option<&[Entity]>| {
+        roll.results
+            .iter()
+            .filter(|(entity, _, _)| held.is_none_or(|held| held.contains(entity)))
+            .filter_map(|(_, _, label)| label.value)
+            .map(|value| value as u8)
+            .collect::<Vec<_>>()
+    };
+    if game.seats.changed(&players) {
+        *game = Farkle::new(game.rules, &players);
+        count_die.0 = false;
+    }
+    // a die fell off the table, so the throw is repeated
+    if game.phase == Phase::Settling && !game.deal && dice.iter().len() != DICE - game.set_aside {
+        game.deal = true;
+    }
+    if game.phase == Phase::Settling
+        && !game.deal
+        && dice.iter().len() == DICE - game.set_aside
+        && dice.iter().all(|counted| counted)
+    {
+        count_die.0 = false;
+        game.phase = if game.rules.scores_any(&values(None)) {
+            Phase::Choosing
+        } else {
+            Phase::Busted
+        };
+    }
+
+    let Some(action) = game.action.take() else {
+        return;
+    };
+    // 0 while the held dice are not a scoring combination
+    let held = game.rules.score(&values(Some(&game.held))).unwrap_or(0);
+    if game.act(action, held, &mut players) {
+        count_die.0 = true;
+    }
+}
+
+// the dice of the last throw are taken off the table, the set aside ones are only kept as points
+fn throw_dice(
+    mut game: ResMut<Farkle>,
+    mut spawner: DieSpawner,
+    mut roll: Single<&mut Roll>,
+    dice: Query<Entity, With<Die>>,
+) {
+    if !game.deal {
+        return;
+    }
+    game.deal = false;
+    for entity in dice.iter() {
+        spawner.commands.entity(entity).despawn();
+    }
+    roll.results.clear();
+    let mut rng = rand::rng();
+    let count = DICE - game.set_aside;
+    for i in 0..count {
+        let angle = i as f32 / count as f32 * TAU + rng.random_range(-0.3..0.3);
+        let translation = Vec3::new(angle.cos(), 3.0, angle.sin());
+        spawner.spawn_with(
+            Transform::from_translation(translation),
+            DieFaces::numbered(),
+        );
+    }
+}
+
+fn farkle_panel(
+    mut contexts: EguiContexts,
+    mut game: ResMut<Farkle>,
+    players: Res<Players>,
+    roll: Single<&Roll>,
+) {
+    let active = players.active().name.as_str();
+    let mut rules = game.rules;
+    let mut action = None;
+    egui::Window::new("Farkle").show(contexts.ctx_mut(), |ui| {
+        egui::Grid::new("scoreboard").show(ui, |ui| {
+            for (i, (player, score)) in players.list.iter().zip(&game.scores).enumerate() {
+                ui.label(if i == players.active { ">" } else { "" });
+                ui.label(player.name.as_str());
+                ui.label(score.to_string());
+                ui.end_row();
+            }
+        });
+        ui.label(format!("{} points this turn", game.turn));
+        match game.phase {
+            Phase::Rolling => {
+                ui.label(format!("{active}, roll {} dice", DICE - game.set_aside));
+                if ui.button("Roll").clicked() {
+                    action = Some(Action::Roll);
+                }
+            }
+            Phase::Settling => {
+                ui.label("the dice are settling");
+            }
+            Phase::Choosing => {
+                ui.label(format!("{active}, hold the dice to set aside"));
+                ui.horizontal(|ui| {
+                    for (entity, _, label) in roll.results.iter() {
+                        let held = game.held.contains(entity);
+                        if ui.selectable_label(held, label.display()).clicked() {
+                            action = Some(Action::Hold(*entity));
+                        }
+                    }
+                });
+                let values = roll
+                    .results
+                    .iter()
+                    .filter(|(entity, _, _)| game.held.contains(entity))
+                    .filter_map(|(_, _, label)| label.value)
+                    .map(|value| value as u8)
+                    .collect::<Vec<_>>();
+                let held = game.rules.score(&values).unwrap_or(0);
+                if held > 0 {
+                    ui.label(format!("holding {held} points"));
+                } else if !values.is_empty() {
+                    ui.label("not every held die scores");
+                }
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(held > 0, egui::Button::new("Keep rolling"))
+                        .clicked()
+                    {
+                        action = Some(Action::KeepRolling);
+                    }
+                    let bankable = held > 0 && game.turn + held >= game.rules.minimum;
+                    if ui
+                        .add_enabled(bankable, egui::Button::new("Bank"))
+                        .clicked()
+                    {
+                        action = Some(Action::Bank);
+                    }
+                });
+            }
+            Phase::Busted => {
+                ui.label(format!("Farkle, {active} loses {} points", game.turn));
+                if ui.button("Next player").clicked() {
+                    action = Some(Action::NextPlayer);
+                }
+            }
+            Phase::Over { winner } => {
+                if let (Some(player), Some(score)) =
+                    (players.list.get(winner), game.scores.get(winner))
+                {
+                    ui.label(format!("{} wins with {score}", player.name));
+                }
+                if ui.button("New game").clicked() {
+                    action = Some(Action::NewGame);
+                }
+            }
+        }
+        ui.collapsing("House rules", |ui| {
+            egui::Grid::new("house rules").show(ui, |ui| {
+                ui.label("target");
+                ui.add(
+                    egui::DragValue::new(&mut rules.target)
+                        .range(1000..=100000)
+                        .speed(50),
+                );
+                ui.end_row();
+                ui.label("minimum to bank");
+                ui.add(
+                    egui::DragValue::new(&mut rules.minimum)
+                        .range(0..=5000)
+                        .speed(50),
+                );
+                ui.end_row();
+                ui.label("straight");
+                ui.add(
+                    egui::DragValue::new(&mut rules.straight)
+                        .range(0..=5000)
+                        .speed(50),
+                );
+                ui.end_row();
+                ui.label("three pairs");
+                ui.add(
+                    egui::DragValue::new(&mut rules.three_pairs)
+                        .range(0..=5000)
+                        .speed(50),
+                );
+                ui.end_row();
+            });
+            ui.checkbox(&mut rules.doubling, "dice beyond a triple double it");
+        });
+    });
+    if rules != game.rules {
+        game.rules = rules;
+    }
+    if action.is_some() {
+        game.action = action;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn held_dice_are_scored() {
+        let rules = HouseRules::default();
+        assert_eq!(rules.score(&[1]), Some(100));
+        assert_eq!(rules.score(&[5, 1, 5]), Some(200));
+        assert_eq!(rules.score(&[2, 2, 2]), Some(200));
+        assert_eq!(rules.score(&[1, 1, 1, 5]), Some(1050));
+        assert_eq!(rules.score(&[4, 4, 4, 4, 4]), Some(1600));
+        assert_eq!(rules.score(&[3, 6, 1, 2, 5, 4]), Some(1500));
+        assert_eq!(rules.score(&[2, 2, 3, 3, 6, 6]), Some(750));
+        assert_eq!(rules.score(&[1, 2]), None);
+        assert_eq!(rules.score(&[2, 2]), None);
+        assert_eq!(rules.score(&[]), None);
+        let rules = HouseRules {
+            three_pairs: 0,
+            doubling: false,
+            ..rules
+        };
+        assert_eq!(rules.score(&[4, 4, 4, 4, 4]), Some(1200));
+        assert_eq!(rules.score(&[2, 2, 3, 3, 6, 6]), None);
+        assert_eq!(rules.score(&[1, 1, 5, 5, 6, 6]), None);
+    }
+
+    #[test]
+    fn throws_without_scoring_dice_bust() {
+        let rules = HouseRules::default();
+        assert!(rules.scores_any(&[2, 3, 4, 6, 5]));
+        assert!(rules.scores_any(&[6, 6, 3, 6]));
+        assert!(rules.scores_any(&[2, 2, 3, 3, 4, 4]));
+        assert!(!rules.scores_any(&[2, 3, 4, 6, 6, 3]));
+        assert!(!rules.scores_any(&[4]));
+    }
+
+    // a game in which the active player holds the given number of dice
+    fn choosing(players: &Players, held: u32) -> Farkle {
+        let mut game = Farkle::new(HouseRules::default(), players);
+        game.phase = Phase::Choosing;
+        game.held = (0..held).map(Entity::from_raw).collect();
+        game
+    }
+
+    #[test]
+    fn dice_are_held_and_thrown() {
+        let mut players = Players::new(2).unwrap();
+        let mut game = Farkle::new(HouseRules::default(), &players);
+        assert!(game.act(Action::Roll, 0, &mut players));
+        assert_eq!((game.phase, game.deal), (Phase::Settling, true));
+        // nothing is thrown twice while the dice settle
+        assert!(!game.act(Action::Roll, 0, &mut players));
+        let mut game = choosing(&players, 2);
+        game.act(Action::Hold(Entity::from_raw(0)), 0, &mut players);
+        assert_eq!(game.held, [Entity::from_raw(1)]);
+        // the held dice have to score before the rest is rolled
+        assert!(!game.act(Action::KeepRolling, 0, &mut players));
+        assert!(game.act(Action::KeepRolling, 100, &mut players));
+        assert_eq!((game.turn, game.set_aside), (100, 1));
+        assert!(game.held.is_empty());
+    }
+
+    #[test]
+    fn hot_dice_are_all_rolled_again() {
+        let mut players = Players::new(2).unwrap();
+        let mut game = choosing(&players, 2);
+        game.set_aside = 4;
+        game.turn = 500;
+        assert!(game.act(Action::KeepRolling, 200, &mut players));
+        assert_eq!((game.turn, game.set_aside), (700, 0));
+        let mut game = choosing(&players, 6);
+        assert!(game.act(Action::KeepRolling, 1500, &mut players));
+        assert_eq!((game.turn, game.set_aside), (1500, 0));
+        assert_eq!(players.active, 0);
+    }
+
+    #[test]
+    fn banking_needs_the_minimum() {
+        let mut players = Players::new(2).unwrap();
+        let mut game = choosing(&players, 1);
+        game.turn = 200;
+        game.act(Action::Bank, 100, &mut players);
+        assert_eq!((game.phase, game.scores[0]), (Phase::Choosing, 0));
+        game.act(Action::Bank, 150, &mut players);
+        assert_eq!(game.scores, [350, 0]);
+        assert_eq!(
+            (game.phase, game.turn, players.active),
+            (Phase::Rolling, 0, 1)
+        );
+        // reaching the target wins the game and a point of the session
+        let mut game = choosing(&players, 1);
+        game.scores[1] = 9900;
+        game.act(Action::Bank, 400, &mut players);
+        assert_eq!(game.phase, Phase::Over { winner: 1 });
+        assert_eq!(players.list[1].score, 1);
+        game.act(Action::NewGame, 0, &mut players);
+        assert_eq!(
+            (game.phase, game.scores.as_slice()),
+            (Phase::Rolling, &[0, 0][..])
+        );
+    }
+
+    #[test]
+    fn busting_loses_the_points_of_the_turn() {
+        let mut players = Players::new(2).unwrap();
+        let mut game = choosing(&players, 0);
+        game.scores[0] = 1000;
+        game.turn = 800;
+        game.set_aside = 3;
+        game.phase = Phase::Busted;
+        // there is nothing left to bank or roll
+        game.act(Action::Bank, 100, &mut players);
+        assert!(!game.act(Action::KeepRolling, 100, &mut players));
+        assert_eq!(game.turn, 800);
+        game.act(Action::NextPlayer, 0, &mut players);
+        assert_eq!(game.scores, [1000, 0]);
+        assert_eq!(
+            (game.phase, game.turn, game.set_aside),
+            (Phase::Rolling, 0, 0)
+        );
+        assert_eq!(players.active, 1);
+    }
+
+    #[test]
+    fn the_scoreboard_outlasts_a_turn() {
+        let players = Players::new(2).unwrap();
+        let mut game = Farkle::new(HouseRules::default(), &players);
+        game.scores[1] = 500;
+        game.turn = 300;
+        game.set_aside = 2;
+        game.next_turn();
+        assert_eq!(game.scores, [0, 500]);
+        assert_eq!(
+            (game.phase, game.turn, game.set_aside),
+            (Phase::Rolling, 0, 0)
+        );
+    }
+}
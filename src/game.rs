@@ -0,0 +1,132 @@
+use crate::die::Die;
+use crate::players::Players;
+use crate::{DieSpawner, Roll};
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use rand::Rng;
+
+// the rules the table is played by, free play only adds up every roll
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum GameMode {
+    #[default]
+    Free,
+    Maexchen,
+    Perudo,
+    Farkle,
+}
+
+impl GameMode {
+    pub const ALL: [GameMode; 4] = [
+        GameMode::Free,
+        GameMode::Maexchen,
+        GameMode::Perudo,
+        GameMode::Farkle,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameMode::Free => "free",
+            GameMode::Maexchen => "maexchen",
+            GameMode::Perudo => "perudo",
+            GameMode::Farkle => "farkle",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        GameMode::ALL
+            .into_iter()
+            .find(|mode| mode.name() == value)
+            .ok_or_else(|| {
+                let names = GameMode::ALL.map(|mode| mode.name()).join(", ");
+                format!("unknown game {value}, expected one of {names}")
+            })
+    }
+}
+
+// the players in seat order, which is how the games keep track of them
+#[derive(PartialEq, Debug)]
+pub struct Seats(Vec<String>);
+
+impl Seats {
+    pub fn new(players: &Players) -> Self {
+        Seats(
+            players
+                .list
+                .iter()
+                .map(|player| player.name.clone())
+                .collect(),
+        )
+    }
+
+    // somebody joined, left or moved, so whatever a game keeps by seat belongs to someone else
+    pub fn changed(&self, players: &Players) -> bool {
+        *self != Seats::new(players)
+    }
+}
+
+// the dice jump up inside their cups and are read once they are asleep again
+pub fn shake_dice<'a>(
+    commands: &mut Commands,
+    dice: impl Iterator<Item = (Entity, Mut<'a, LinearVelocity>, Mut<'a, AngularVelocity>)>,
+) {
+    let mut rng = rand::rng();
+    for (entity, mut linear_velocity, mut angular_velocity) in dice {
+        commands.entity(entity).remove::<Sleeping>();
+        linear_velocity.0 = Vec3::new(
+            rng.random_range(-1.0..1.0),
+            10.0,
+            rng.random_range(-1.0..1.0),
+        );
+        angular_velocity.0 = Vec3::new(
+            rng.random_range(-1.0..1.0),
+            rng.random_range(-1.0..1.0),
+            rng.random_range(-1.0..1.0),
+        ) * 15.0;
+    }
+}
+
+// takes the previous dice off the table before new ones are dealt, like the cups handed on;
+// false while a player has no cup yet, as the cups are seated a frame after the players change
+pub(crate) fn clear_for_deal(
+    spawner: &mut DieSpawner,
+    roll: &mut Roll,
+    dice: &Query<Entity, With<Die>>,
+    cups: usize,
+) -> bool {
+    if cups != spawner.players.list.len() {
+        return false;
+    }
+    for entity in dice.iter() {
+        spawner.commands.entity(entity).despawn();
+    }
+    roll.results.clear();
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn game_modes_round_trip() {
+        for mode in GameMode::ALL {
+            assert_eq!(GameMode::parse(mode.name()), Ok(mode));
+        }
+        assert!(GameMode::parse("chess").is_err());
+    }
+
+    #[test]
+    fn seats_follow_the_players() {
+        let mut players = Players::new(3).unwrap();
+        let seats = Seats::new(&players);
+        assert!(!seats.changed(&players));
+        players.next();
+        assert!(!seats.changed(&players));
+        players.move_up(2);
+        assert!(seats.changed(&players));
+        players.move_up(2);
+        assert!(!seats.changed(&players));
+        players.remove(1).unwrap();
+        assert!(seats.changed(&players));
+    }
+}
@@ -1,15 +1,70 @@
 use bevy::asset::RenderAssetUsages;
-use bevy::prelude::ops::{cos, sin};
+use bevy::prelude::ops::{cos, sin, tan};
 use bevy::prelude::*;
-use bevy::render::mesh::{Indices, PrimitiveTopology, VertexAttributeValues};
+use bevy::render::mesh::{
+    GenerateTangentsError, Indices, PrimitiveTopology, VertexAttributeValues,
+};
 use std::collections::{HashMap, HashSet};
-use std::fmt::Error;
+use std::fmt::{Display, Formatter};
 
-pub fn create_icosphere(iterations: u8) -> Mesh {
-    assert!(iterations <= 8, "iterations must be between 0 and 8");
+#[derive(Debug)]
+pub enum GeometryError {
+    MissingPositions,
+    MissingIndices,
+    MissingAttribute(&'static str),
+    NonTriangleTopology,
+    DegeneratePlane,
+    TangentGeneration(GenerateTangentsError),
+    // a cut triangle has fewer than two intersections, which only rounding can cause
+    InconsistentIntersection,
+    ColliderGeneration,
+    InvalidParameters(String),
+}
+
+impl Display for GeometryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GeometryError::MissingPositions => write!(f, "mesh has no float3 positions"),
+            GeometryError::MissingIndices => write!(f, "mesh has no indices"),
+            GeometryError::MissingAttribute(name) => write!(f, "mesh has no {name}"),
+            GeometryError::NonTriangleTopology => write!(f, "mesh is not a triangle list"),
+            GeometryError::DegeneratePlane => write!(f, "plane normal has no direction"),
+            GeometryError::TangentGeneration(error) => {
+                write!(f, "failed to generate tangents: {error}")
+            }
+            GeometryError::InconsistentIntersection => {
+                write!(f, "a triangle is cut by the plane at fewer than two edges")
+            }
+            GeometryError::ColliderGeneration => write!(f, "no collider fits the mesh"),
+            GeometryError::InvalidParameters(reason) => write!(f, "invalid parameters: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for GeometryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GeometryError::TangentGeneration(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<GenerateTangentsError> for GeometryError {
+    fn from(error: GenerateTangentsError) -> Self {
+        GeometryError::TangentGeneration(error)
+    }
+}
+
+pub fn create_icosphere(iterations: u8) -> Result<Mesh, GeometryError> {
+    if iterations > 8 {
+        return Err(GeometryError::InvalidParameters(format!(
+            "iterations must be between 0 and 8, got {iterations}"
+        )));
+    }
 
     let icosahedron = generate_regular_icosahedron();
-    let (vertices, mut indices) = extract_mesh_attributes(&icosahedron).expect("valid mesh");
+    let (vertices, mut indices) = extract_mesh_attributes(&icosahedron)?;
     let mut vertices = vertices
         .iter()
         .map(project_to_unit_circle)
@@ -34,7 +89,7 @@ pub fn create_icosphere(iterations: u8) -> Mesh {
 
         indices = new_indices;
     }
-    construct_mesh(vertices, indices)
+    Ok(construct_mesh(vertices, indices))
 }
 
 type Vertex = [f32; 3];
@@ -123,21 +178,53 @@ pub fn generate_regular_icosahedron() -> Mesh {
     ))
 }
 
-pub fn create_d6(depth: u8, threshold: f32, size: f32) -> Mesh {
-    let mut d6 = create_icosphere(depth);
-    let orientations = vec![
-        (2, Vec3::NEG_X, Vec3::Z, Vec3::NEG_Y), // left
-        (5, Vec3::X, Vec3::Z, Vec3::Y),         // right
-        (6, Vec3::Y, Vec3::NEG_Z, Vec3::X),     // up
-        (1, Vec3::NEG_Y, Vec3::Z, Vec3::X),     // down
-        (4, Vec3::Z, Vec3::Y, Vec3::X),         // front
-        (3, Vec3::NEG_Z, Vec3::Y, Vec3::NEG_X), // back
-    ];
+// the normal, the up direction of the label and its right side for the left, right, up, down,
+// front and back face
+const D6_ORIENTATIONS: [(Vec3, Vec3, Vec3); 6] = [
+    (Vec3::NEG_X, Vec3::Z, Vec3::NEG_Y),
+    (Vec3::X, Vec3::Z, Vec3::Y),
+    (Vec3::Y, Vec3::NEG_Z, Vec3::X),
+    (Vec3::NEG_Y, Vec3::Z, Vec3::X),
+    (Vec3::Z, Vec3::Y, Vec3::X),
+    (Vec3::NEG_Z, Vec3::Y, Vec3::NEG_X),
+];
+
+fn validate_d6(size: f32, faces: &[u8; 6]) -> Result<(), GeometryError> {
+    if !(size > 0.0 && size.is_finite()) {
+        return Err(GeometryError::InvalidParameters(format!(
+            "size must be positive, got {size}"
+        )));
+    }
+    if let Some(face) = faces.iter().find(|face| !(1..=6).contains(*face)) {
+        return Err(GeometryError::InvalidParameters(format!(
+            "faces must be between 1 and 6, got {face}"
+        )));
+    }
+    Ok(())
+}
+
+// faces are the values on the left, right, up, down, front and back side
+pub fn create_d6(
+    depth: u8,
+    threshold: f32,
+    size: f32,
+    faces: [u8; 6],
+) -> Result<Mesh, GeometryError> {
+    // below 1/sqrt(2) the circles of neighbouring faces would overlap
+    if !(threshold > std::f32::consts::FRAC_1_SQRT_2 && threshold < 1.0) {
+        return Err(GeometryError::InvalidParameters(format!(
+            "threshold must be between 1/sqrt(2) and 1, got {threshold}"
+        )));
+    }
+    validate_d6(size, &faces)?;
+    let mut d6 = create_icosphere(depth)?;
     let mut uvs = vec![[0.0, 0.0]; d6.count_vertices()];
-    for (die_face, plane_normal, reference, clockwise_normal) in orientations {
+    for (die_face, (plane_normal, reference, clockwise_normal)) in
+        faces.into_iter().zip(D6_ORIENTATIONS)
+    {
         let center = plane_normal * threshold;
         let circle_start_index = d6.count_vertices();
-        d6 = intersect_mesh_with_plane(d6, center, plane_normal).expect("valid mesh");
+        d6 = intersect_mesh_with_plane(d6, center, plane_normal)?;
         let circle_count = d6.count_vertices() - circle_start_index;
         uvs.extend(vec![[0.0, 0.0]; circle_count]);
         d6 = fill_circle(
@@ -145,7 +232,7 @@ pub fn create_d6(depth: u8, threshold: f32, size: f32) -> Mesh {
             (center, reference * threshold, clockwise_normal * threshold),
             circle_start_index,
             &mut uvs,
-        );
+        )?;
         for i in uvs.len() - circle_count - 1..uvs.len() {
             uvs[i][0] = (die_face - 1) as f32 * 1.0 / 6.0 + uvs[i][0] / 6.0;
         }
@@ -154,8 +241,8 @@ pub fn create_d6(depth: u8, threshold: f32, size: f32) -> Mesh {
         d6,
         |vertex| vertex.iter().any(|c| c.abs() > threshold),
         &mut uvs,
-    );
-    let (vertices, indices) = extract_mesh_attributes(&d6).expect("valid mesh");
+    )?;
+    let (vertices, indices) = extract_mesh_attributes(&d6)?;
 
     let scale_factor = size / (2.0 * threshold);
     let scaled_vertices = vertices
@@ -163,15 +250,221 @@ pub fn create_d6(depth: u8, threshold: f32, size: f32) -> Mesh {
         .map(|[x, y, z]| [x * scale_factor, y * scale_factor, z * scale_factor])
         .collect::<Vec<_>>();
 
-    construct_mesh(scaled_vertices, indices)
+    Ok(construct_mesh(scaled_vertices, indices)
         .with_computed_normals()
         .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
-        .with_generated_tangents()
-        .expect("generated tangents")
+        .with_generated_tangents()?)
 }
 
-fn intersect_mesh_with_plane(mesh: Mesh, plane_point: Vec3, plane_normal: Vec3) -> Result<Mesh> {
-    let (mut vertices, indices) = extract_mesh_attributes(&mesh).ok_or(Error::default())?;
+// edges are rounded with edge_radius and the corners are blended towards corner_radius,
+// both radii at zero give the sharp edges of casino dice; segments subdivide each rounding
+pub fn create_rounded_d6(
+    segments: u8,
+    (edge_radius, corner_radius): (f32, f32),
+    size: f32,
+    faces: [u8; 6],
+) -> Result<Mesh, GeometryError> {
+    validate_d6(size, &faces)?;
+    let half = size / 2.0;
+    if !(0.0..=corner_radius).contains(&edge_radius) || corner_radius > half {
+        return Err(GeometryError::InvalidParameters(format!(
+            "radii must satisfy 0 <= edge <= corner <= size / 2, got {edge_radius} and {corner_radius}"
+        )));
+    }
+    if !(1..=32).contains(&segments) {
+        return Err(GeometryError::InvalidParameters(format!(
+            "segments must be between 1 and 32, got {segments}"
+        )));
+    }
+
+    // the grid points in the rounded band are spaced so that the arc angles are even
+    let mut steps = (0..=segments)
+        .map(|i| {
+            let angle = std::f32::consts::FRAC_PI_4 * i as f32 / segments as f32;
+            half - corner_radius + corner_radius * tan(angle)
+        })
+        .collect::<Vec<_>>();
+    steps.dedup();
+    let mut grid = steps.iter().rev().map(|step| -step).collect::<Vec<_>>();
+    grid.extend(steps);
+    grid.dedup();
+
+    let weight = |c: f32| {
+        if corner_radius > 0.0 {
+            ((c.abs() - (half - corner_radius)) / corner_radius).clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    };
+    let (mut vertices, mut normals, mut uvs, mut indices) = (vec![], vec![], vec![], vec![]);
+    for (die_face, (plane_normal, reference, clockwise_normal)) in
+        faces.into_iter().zip(D6_ORIENTATIONS)
+    {
+        let start_index = vertices.len();
+        for t in &grid {
+            for s in &grid {
+                let point = plane_normal * half + clockwise_normal * *s + reference * *t;
+                let blend = weight(point.x) * weight(point.y) * weight(point.z);
+                let radius = edge_radius + (corner_radius - edge_radius) * blend;
+                let inner = point.clamp(Vec3::splat(radius - half), Vec3::splat(half - radius));
+                let normal = (point - inner).try_normalize().unwrap_or(plane_normal);
+                vertices.push((inner + normal * radius).to_array());
+                normals.push(normal.to_array());
+                uvs.push([
+                    ((die_face - 1) as f32 + (s / half + 1.0) / 2.0) / 6.0,
+                    (1.0 - t / half) / 2.0,
+                ]);
+            }
+        }
+        let row = grid.len();
+        for t in 0..row - 1 {
+            for s in 0..row - 1 {
+                let index = start_index + t * row + s;
+                indices.extend([index, index + 1, index + row + 1]);
+                indices.extend([index, index + row + 1, index + row]);
+            }
+        }
+    }
+
+    Ok(construct_mesh(vertices, indices)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+        .with_generated_tangents()?)
+}
+
+// carves markings into a mesh by moving every vertex inwards along its normal by depth(uv);
+// edges longer than max_edge are split first, so that the markings have enough vertices to
+// follow, and as splitting only depends on the edge itself, uv seams stay closed
+pub fn engrave(
+    mesh: &Mesh,
+    max_edge: f32,
+    depth: impl Fn(Vec2) -> f32,
+) -> Result<Mesh, GeometryError> {
+    if !(max_edge > 0.0 && max_edge.is_finite()) {
+        return Err(GeometryError::InvalidParameters(format!(
+            "max edge must be positive, got {max_edge}"
+        )));
+    }
+    let (vertices, mut indices) = extract_mesh_attributes(mesh)?;
+    let Some(VertexAttributeValues::Float32x3(normals)) = mesh.attribute(Mesh::ATTRIBUTE_NORMAL)
+    else {
+        return Err(GeometryError::MissingAttribute("normals"));
+    };
+    let Some(VertexAttributeValues::Float32x2(uvs)) = mesh.attribute(Mesh::ATTRIBUTE_UV_0) else {
+        return Err(GeometryError::MissingAttribute("uvs"));
+    };
+    let mut positions = vertices
+        .iter()
+        .map(|v| Vec3::from_array(*v))
+        .collect::<Vec<_>>();
+    let mut normals = normals
+        .iter()
+        .map(|n| Vec3::from_array(*n))
+        .collect::<Vec<_>>();
+    let mut uvs = uvs
+        .iter()
+        .map(|uv| Vec2::from_array(*uv))
+        .collect::<Vec<_>>();
+
+    // every pass halves the long edges, the limit only guards against slivers
+    for _ in 0..32 {
+        let mut midpoints = HashMap::new();
+        for triangle in indices.chunks(3) {
+            for i in 0..3 {
+                let (a, b) = (triangle[i], triangle[(i + 1) % 3]);
+                let key = (a.min(b), a.max(b));
+                if midpoints.contains_key(&key) || positions[a].distance(positions[b]) <= max_edge {
+                    continue;
+                }
+                midpoints.insert(key, positions.len());
+                // sums do not depend on the order, so both sides of a seam get the same point
+                positions.push((positions[a] + positions[b]) * 0.5);
+                normals.push((normals[a] + normals[b]).normalize_or(normals[a]));
+                uvs.push((uvs[a] + uvs[b]) * 0.5);
+            }
+        }
+        if midpoints.is_empty() {
+            break;
+        }
+
+        let mut split = Vec::with_capacity(indices.len() * 4);
+        for triangle in indices.chunks(3) {
+            let middles = [0, 1, 2].map(|i| {
+                let (a, b) = (triangle[i], triangle[(i + 1) % 3]);
+                midpoints.get(&(a.min(b), a.max(b))).copied()
+            });
+            // rotated so that the first edge is split and, if two are, the last one is not
+            let rotation = (0..3)
+                .find(|r| middles[*r].is_some() && middles[(r + 2) % 3].is_none())
+                .unwrap_or(0);
+            let [a, b, c] = [0, 1, 2].map(|i| triangle[(i + rotation) % 3]);
+            let [ab, bc, ca] = [0, 1, 2].map(|i| middles[(i + rotation) % 3]);
+            match (ab, bc, ca) {
+                (Some(ab), Some(bc), Some(ca)) => {
+                    split.extend([a, ab, ca, ab, b, bc, ca, bc, c, ab, bc, ca]);
+                }
+                (Some(ab), Some(bc), None) => {
+                    split.extend([ab, b, bc]);
+                    // the remaining quad is cut along its shorter diagonal
+                    if positions[a].distance(positions[bc]) < positions[ab].distance(positions[c]) {
+                        split.extend([a, ab, bc, a, bc, c]);
+                    } else {
+                        split.extend([a, ab, c, ab, bc, c]);
+                    }
+                }
+                (Some(ab), None, None) => split.extend([a, ab, c, ab, b, c]),
+                _ => split.extend([a, b, c]),
+            }
+        }
+        indices = split;
+    }
+
+    let depths = uvs.iter().map(|uv| depth(*uv)).collect::<Vec<_>>();
+    for ((position, normal), depth) in positions.iter_mut().zip(&normals).zip(&depths) {
+        *position -= *normal * *depth;
+    }
+    // vertices of carved triangles get new normals, elsewhere the original shading is kept
+    let mut carved = vec![false; positions.len()];
+    for triangle in indices.chunks(3) {
+        if triangle.iter().any(|i| depths[*i] != 0.0) {
+            triangle.iter().for_each(|i| carved[*i] = true);
+        }
+    }
+    let mut carved_normals = vec![Vec3::ZERO; positions.len()];
+    for triangle in indices.chunks(3) {
+        if triangle.iter().any(|i| carved[*i]) {
+            let [a, b, c] = [0, 1, 2].map(|i| positions[triangle[i]]);
+            let normal = (b - a).cross(c - a);
+            triangle.iter().for_each(|i| carved_normals[*i] += normal);
+        }
+    }
+    let normals = (0..positions.len())
+        .map(|i| {
+            if carved[i] {
+                carved_normals[i].normalize_or(normals[i]).to_array()
+            } else {
+                normals[i].to_array()
+            }
+        })
+        .collect::<Vec<_>>();
+    let vertices = positions.iter().map(|p| p.to_array()).collect();
+    let uvs = uvs.iter().map(|uv| uv.to_array()).collect::<Vec<_>>();
+
+    Ok(construct_mesh(vertices, indices)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+        .with_generated_tangents()?)
+}
+
+fn intersect_mesh_with_plane(
+    mesh: Mesh,
+    plane_point: Vec3,
+    plane_normal: Vec3,
+) -> Result<Mesh, GeometryError> {
+    if plane_normal.length_squared() <= f32::EPSILON || !plane_normal.is_finite() {
+        return Err(GeometryError::DegeneratePlane);
+    }
+    let (mut vertices, indices) = extract_mesh_attributes(&mesh)?;
     let mut index_cache = HashMap::new();
     let mut new_indices = vec![];
 
@@ -210,11 +503,12 @@ fn intersect_mesh_with_plane(mesh: Mesh, plane_point: Vec3, plane_normal: Vec3)
             } else {
                 triangle[(t + 1) % 3].0
             };
-            let mut x = (t + 2) % 3;
-            while triangle[x].1 == None {
-                x = (x + 2) % 3;
-            }
-            let i3 = triangle[x].1.expect("index");
+            let Some(i3) = [(t + 2) % 3, (t + 1) % 3, t]
+                .into_iter()
+                .find_map(|x| triangle[x].1)
+            else {
+                return Err(GeometryError::InconsistentIntersection);
+            };
             new_indices.extend([i1, i2, i3]);
         }
     }
@@ -249,7 +543,7 @@ fn intersect_line_with_plane(
         return None;
     }
     let factor = plane_normal.dot(l1 - plane_point) / -dot;
-    if factor < 0.0 || factor > 1.0 {
+    if !(0.0..=1.0).contains(&factor) {
         return None;
     }
     Some(l1 + line * factor)
@@ -263,8 +557,8 @@ fn remove_if<Predicate: Fn(Vertex) -> bool>(
     mesh: Mesh,
     predicate: Predicate,
     uvs: &mut Vec<[f32; 2]>,
-) -> Mesh {
-    let (vertices, indices) = extract_mesh_attributes(&mesh).expect("valid mesh");
+) -> Result<Mesh, GeometryError> {
+    let (vertices, indices) = extract_mesh_attributes(&mesh)?;
     let mut index_offsets = vec![];
     let mut new_vertices = vec![];
     let mut new_indices = vec![];
@@ -283,32 +577,33 @@ fn remove_if<Predicate: Fn(Vertex) -> bool>(
     }
     *uvs = new_uvs;
     for i in (0..indices.len()).step_by(3) {
-        let indices = vec![indices[i], indices[i + 1], indices[i + 2]];
+        let indices = [indices[i], indices[i + 1], indices[i + 2]];
         if indices.iter().all(|i| !removed.contains(i)) {
             new_indices.extend(indices.iter().map(|i| i - index_offsets[*i]));
         }
     }
-    construct_mesh(new_vertices, new_indices)
+    Ok(construct_mesh(new_vertices, new_indices))
 }
 
 fn fill_circle(
     mesh: Mesh,
     (center, reference, clockwise_normal): (Vec3, Vec3, Vec3),
-    mut start_index: usize,
+    start_index: usize,
     uvs: &mut Vec<[f32; 2]>,
-) -> Mesh {
+) -> Result<Mesh, GeometryError> {
     let reference = center + reference;
     let (mut clockwise, mut counter) = (vec![], vec![]);
-    let (mut vertices, mut indices) = extract_mesh_attributes(&mesh).expect("valid mesh");
+    let (mut vertices, mut indices) = extract_mesh_attributes(&mesh)?;
 
     let len = vertices.len();
     for i in start_index..len {
         vertices.push(vertices[i]);
         uvs.push([0.0, 0.0]);
-        start_index = i + 1;
     }
-    for index in start_index..vertices.len() {
-        let vertex = Vec3::from_array(vertices[index]);
+    // only the copies are sorted around the circle
+    let start_index = start_index.max(len);
+    for (index, vertex) in vertices.iter().enumerate().skip(start_index) {
+        let vertex = Vec3::from_array(*vertex);
         if vertex.distance(center + clockwise_normal) < vertex.distance(center - clockwise_normal) {
             clockwise.push(index);
         } else {
@@ -318,8 +613,7 @@ fn fill_circle(
     let sort_by_angle = |a, b| {
         reference
             .angle_between(Vec3::from_array(vertices[a]))
-            .partial_cmp(&reference.angle_between(Vec3::from_array(vertices[b])))
-            .expect("comparable")
+            .total_cmp(&reference.angle_between(Vec3::from_array(vertices[b])))
     };
     clockwise.sort_by(|a, b| sort_by_angle(*b, *a));
     counter.sort_by(|a, b| sort_by_angle(*a, *b));
@@ -343,17 +637,24 @@ fn fill_circle(
     vertices.push(center.to_array());
     uvs.push([0.5, 0.5]);
 
-    construct_mesh(vertices, indices)
+    Ok(construct_mesh(vertices, indices))
 }
 
-fn extract_mesh_attributes(mesh: &Mesh) -> Option<(Vec<Vertex>, Vec<usize>)> {
-    Some((
-        Vec::from(
-            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
-                .and_then(VertexAttributeValues::as_float3)?,
-        ),
-        Vec::from_iter(mesh.indices()?.iter()),
-    ))
+pub(crate) fn extract_mesh_attributes(
+    mesh: &Mesh,
+) -> Result<(Vec<Vertex>, Vec<usize>), GeometryError> {
+    if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+        return Err(GeometryError::NonTriangleTopology);
+    }
+    let vertices = mesh
+        .attribute(Mesh::ATTRIBUTE_POSITION)
+        .and_then(VertexAttributeValues::as_float3)
+        .ok_or(GeometryError::MissingPositions)?;
+    let indices = mesh.indices().ok_or(GeometryError::MissingIndices)?;
+    if indices.len() % 3 != 0 {
+        return Err(GeometryError::NonTriangleTopology);
+    }
+    Ok((Vec::from(vertices), Vec::from_iter(indices.iter())))
 }
 
 fn construct_mesh(vertices: Vec<Vertex>, indices: Vec<usize>) -> Mesh {
@@ -364,3 +665,437 @@ fn construct_mesh(vertices: Vec<Vertex>, indices: Vec<usize>) -> Mesh {
     .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, vertices)
     .with_inserted_indices(Indices::U32(indices.iter().map(|i| *i as u32).collect()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::{AXES, FaceLayout};
+
+    fn uvs(mesh: &Mesh) -> Vec<[f32; 2]> {
+        match mesh.attribute(Mesh::ATTRIBUTE_UV_0) {
+            Some(VertexAttributeValues::Float32x2(uvs)) => uvs.clone(),
+            _ => panic!("mesh has no uvs"),
+        }
+    }
+
+    fn triangle_normal(vertices: &[Vertex], triangle: &[usize]) -> Vec3 {
+        let [a, b, c] = [0, 1, 2].map(|i| Vec3::from_array(vertices[triangle[i]]));
+        (b - a).cross(c - a)
+    }
+
+    #[test]
+    fn icosphere_counts() {
+        for iterations in 0..=5 {
+            let sphere = create_icosphere(iterations).unwrap();
+            let (vertices, indices) = extract_mesh_attributes(&sphere).unwrap();
+            let factor = 4usize.pow(iterations as u32);
+            assert_eq!(indices.len() / 3, 20 * factor);
+            assert_eq!(vertices.len(), 10 * factor + 2);
+        }
+    }
+
+    #[test]
+    fn icosphere_vertices_are_on_unit_sphere() {
+        for iterations in 0..=4 {
+            let sphere = create_icosphere(iterations).unwrap();
+            let (vertices, _) = extract_mesh_attributes(&sphere).unwrap();
+            for vertex in vertices {
+                assert!((Vec3::from_array(vertex).length() - 1.0).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn icosphere_triangles_face_outwards() {
+        let sphere = create_icosphere(2).unwrap();
+        let (vertices, indices) = extract_mesh_attributes(&sphere).unwrap();
+        for triangle in indices.chunks(3) {
+            let center = triangle
+                .iter()
+                .map(|i| Vec3::from_array(vertices[*i]))
+                .sum::<Vec3>();
+            assert!(triangle_normal(&vertices, triangle).dot(center) > 0.0);
+        }
+    }
+
+    #[test]
+    fn icosphere_rejects_too_many_iterations() {
+        assert!(matches!(
+            create_icosphere(9),
+            Err(GeometryError::InvalidParameters(_))
+        ));
+    }
+
+    #[test]
+    fn line_crossing_plane() {
+        let intersection = intersect_line_with_plane(
+            (Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 3.0, 0.0)),
+            Vec3::Y,
+            Vec3::Y,
+        );
+        assert_eq!(intersection, Some(Vec3::Y));
+    }
+
+    #[test]
+    fn line_parallel_to_plane() {
+        let line = (Vec3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, 1.0, 0.0));
+        assert_eq!(intersect_line_with_plane(line, Vec3::Y, Vec3::Y), None);
+    }
+
+    #[test]
+    fn line_ending_before_plane() {
+        let line = (Vec3::ZERO, Vec3::new(0.0, 0.5, 0.0));
+        assert_eq!(intersect_line_with_plane(line, Vec3::Y, Vec3::Y), None);
+        let reversed = (Vec3::new(0.0, 0.5, 0.0), Vec3::ZERO);
+        assert_eq!(intersect_line_with_plane(reversed, Vec3::Y, Vec3::Y), None);
+    }
+
+    #[test]
+    fn line_touching_plane_with_endpoint() {
+        let line = (Vec3::ZERO, Vec3::Y);
+        assert_eq!(
+            intersect_line_with_plane(line, Vec3::Y, Vec3::Y),
+            Some(Vec3::Y)
+        );
+    }
+
+    #[test]
+    fn triangle_crossing_plane() {
+        let vertices = [[0.0, 0.0, 0.0], [2.0, 0.0, 0.0], [0.0, 2.0, 0.0]];
+        let intersections = intersect_triangle_with_plane(&vertices, &[0, 1, 2], Vec3::X, Vec3::X);
+        assert_eq!(
+            intersections,
+            vec![Some(Vec3::X), Some(Vec3::new(1.0, 1.0, 0.0)), None]
+        );
+        assert!(needs_triangulation(&intersections));
+    }
+
+    #[test]
+    fn triangle_beside_plane() {
+        let vertices = [[0.0, 0.0, 0.0], [0.5, 0.0, 0.0], [0.0, 2.0, 0.0]];
+        let intersections = intersect_triangle_with_plane(&vertices, &[0, 1, 2], Vec3::X, Vec3::X);
+        assert_eq!(intersections, vec![None, None, None]);
+        assert!(!needs_triangulation(&intersections));
+    }
+
+    #[test]
+    fn intersecting_keeps_surface_area() {
+        let sphere = create_icosphere(2).unwrap();
+        let area = |mesh: &Mesh| {
+            let (vertices, indices) = extract_mesh_attributes(mesh).unwrap();
+            indices
+                .chunks(3)
+                .map(|triangle| triangle_normal(&vertices, triangle).length() / 2.0)
+                .sum::<f32>()
+        };
+        let cut = intersect_mesh_with_plane(sphere.clone(), Vec3::Y * 0.5, Vec3::Y).unwrap();
+        assert!((area(&sphere) - area(&cut)).abs() < 1e-4);
+        assert!(cut.count_vertices() > sphere.count_vertices());
+    }
+
+    #[test]
+    fn intersecting_with_degenerate_plane() {
+        let sphere = create_icosphere(0).unwrap();
+        assert!(matches!(
+            intersect_mesh_with_plane(sphere, Vec3::ZERO, Vec3::ZERO),
+            Err(GeometryError::DegeneratePlane)
+        ));
+    }
+
+    #[test]
+    fn remove_if_remaps_indices() {
+        let vertices = vec![
+            [0.0, 0.0, 0.0],
+            [5.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [1.0, 1.0, 0.0],
+        ];
+        let indices = vec![0, 2, 3, 1, 2, 3, 2, 4, 3];
+        let mut uvs = vec![[0.0, 0.0], [0.1, 0.1], [0.2, 0.2], [0.3, 0.3], [0.4, 0.4]];
+        let mesh = remove_if(
+            construct_mesh(vertices, indices),
+            |vertex| vertex[0] > 2.0,
+            &mut uvs,
+        )
+        .unwrap();
+        let (vertices, indices) = extract_mesh_attributes(&mesh).unwrap();
+        assert_eq!(
+            vertices,
+            vec![
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0],
+                [1.0, 1.0, 0.0]
+            ]
+        );
+        assert_eq!(indices, vec![0, 1, 2, 1, 3, 2]);
+        assert_eq!(uvs, vec![[0.0, 0.0], [0.2, 0.2], [0.3, 0.3], [0.4, 0.4]]);
+    }
+
+    #[test]
+    fn fill_circle_winding_and_uvs() {
+        let threshold = 0.72;
+        let center = Vec3::Y * threshold;
+        let sphere = create_icosphere(3).unwrap();
+        let start_index = sphere.count_vertices();
+        let cut = intersect_mesh_with_plane(sphere, center, Vec3::Y).unwrap();
+        let mut uvs = vec![[0.0, 0.0]; cut.count_vertices()];
+        let filled = fill_circle(
+            cut,
+            (center, Vec3::NEG_Z * threshold, Vec3::X * threshold),
+            start_index,
+            &mut uvs,
+        )
+        .unwrap();
+        let (vertices, indices) = extract_mesh_attributes(&filled).unwrap();
+        assert_eq!(uvs.len(), vertices.len());
+
+        let center_index = vertices.len() - 1;
+        let fan = indices
+            .chunks(3)
+            .filter(|triangle| triangle[2] == center_index)
+            .collect::<Vec<_>>();
+        assert!(!fan.is_empty());
+        for triangle in fan {
+            assert!(triangle_normal(&vertices, triangle).dot(Vec3::Y) > 0.0);
+            for index in triangle {
+                let [u, v] = uvs[*index];
+                assert!((0.0..=1.0).contains(&u) && (0.0..=1.0).contains(&v));
+                // the circle is inscribed into the uv square
+                assert!(Vec2::new(u, v).distance(Vec2::splat(0.5)) <= 0.5 + 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn d6_fits_into_size_over_parameter_sweep() {
+        for depth in 2..=4 {
+            for threshold in [0.72, 0.8, 0.9] {
+                for size in [0.1, 0.6, 3.0] {
+                    let d6 =
+                        create_d6(depth, threshold, size, FaceLayout::WESTERN.values()).unwrap();
+                    let (vertices, _) = extract_mesh_attributes(&d6).unwrap();
+                    let (min, max) =
+                        vertices
+                            .iter()
+                            .fold((Vec3::MAX, Vec3::MIN), |(min, max), vertex| {
+                                let vertex = Vec3::from_array(*vertex);
+                                (min.min(vertex), max.max(vertex))
+                            });
+                    let extent = Vec3::splat(size);
+                    assert!(
+                        (max - min - extent).abs().max_element() < size * 1e-4,
+                        "{depth} {threshold} {size}: {min} {max}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn d6_uvs_are_in_the_sixth_of_their_face_over_parameter_sweep() {
+        for faces in [
+            FaceLayout::WESTERN.values(),
+            [5, 2, 6, 1, 4, 3],
+            [1, 2, 3, 4, 5, 6],
+        ] {
+            for depth in 2..=4 {
+                for threshold in [0.72, 0.8, 0.9] {
+                    let size = 0.6;
+                    let d6 = create_d6(depth, threshold, size, faces).unwrap();
+                    let (vertices, indices) = extract_mesh_attributes(&d6).unwrap();
+                    let uvs = uvs(&d6);
+                    for triangle in indices.chunks(3) {
+                        let normal = triangle_normal(&vertices, triangle).normalize();
+                        let Some(axis) = AXES.iter().position(|axis| axis.dot(normal) > 0.9999)
+                        else {
+                            continue;
+                        };
+                        // only triangles on the flat face carry the face texture
+                        let on_face = triangle.iter().all(|i| {
+                            (Vec3::from_array(vertices[*i]).dot(AXES[axis]) - size / 2.0).abs()
+                                < 1e-5
+                        });
+                        if !on_face {
+                            continue;
+                        }
+                        let sixth = (faces[axis] - 1) as f32 / 6.0;
+                        for index in triangle {
+                            let u = uvs[*index][0];
+                            assert!(
+                                u >= sixth - 1e-5 && u <= sixth + 1.0 / 6.0 + 1e-5,
+                                "{faces:?} {depth} {threshold}: {u} not in face {}",
+                                faces[axis]
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn d6_has_normals_uvs_and_tangents() {
+        let d6 = create_d6(3, 0.72, 0.6, FaceLayout::WESTERN.values()).unwrap();
+        let count = d6.count_vertices();
+        assert_eq!(uvs(&d6).len(), count);
+        assert!(d6.attribute(Mesh::ATTRIBUTE_NORMAL).is_some());
+        assert!(d6.attribute(Mesh::ATTRIBUTE_TANGENT).is_some());
+    }
+
+    #[test]
+    fn casino_d6_is_a_cube() {
+        let d6 = create_rounded_d6(4, (0.0, 0.0), 0.6, FaceLayout::WESTERN.values()).unwrap();
+        let (vertices, indices) = extract_mesh_attributes(&d6).unwrap();
+        assert_eq!(vertices.len(), 24);
+        assert_eq!(indices.len(), 36);
+        for vertex in vertices {
+            assert!(vertex.iter().all(|c| (c.abs() - 0.3).abs() < 1e-6));
+        }
+    }
+
+    #[test]
+    fn rounded_d6_fits_into_size_over_parameter_sweep() {
+        for segments in [1, 2, 4, 8] {
+            for radii in [
+                (0.0, 0.0),
+                (0.05, 0.05),
+                (0.0, 0.1),
+                (0.06, 0.12),
+                (0.3, 0.3),
+            ] {
+                let d6 =
+                    create_rounded_d6(segments, radii, 0.6, FaceLayout::WESTERN.values()).unwrap();
+                let (vertices, _) = extract_mesh_attributes(&d6).unwrap();
+                let max = vertices.iter().fold(Vec3::ZERO, |max, vertex| {
+                    max.max(Vec3::from_array(*vertex).abs())
+                });
+                assert!(
+                    (max - Vec3::splat(0.3)).abs().max_element() < 1e-5,
+                    "{segments} {radii:?}: {max}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rounded_d6_with_equal_radii_has_round_edges() {
+        let (radius, half) = (0.1, 0.3);
+        let d6 = create_rounded_d6(
+            8,
+            (radius, radius),
+            2.0 * half,
+            FaceLayout::WESTERN.values(),
+        )
+        .unwrap();
+        let (vertices, _) = extract_mesh_attributes(&d6).unwrap();
+        let inner = Vec3::splat(half - radius);
+        for vertex in vertices {
+            let vertex = Vec3::from_array(vertex);
+            let distance = vertex.abs() - inner;
+            // every point is on the surface of the inner cube grown by the radius
+            assert!((distance.max(Vec3::ZERO).length() - radius).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn rounded_d6_uvs_are_in_the_sixth_of_their_face() {
+        let d6 = create_rounded_d6(4, (0.05, 0.1), 0.6, FaceLayout::WESTERN.values()).unwrap();
+        let (_, indices) = extract_mesh_attributes(&d6).unwrap();
+        let uvs = uvs(&d6);
+        // the faces are generated one after another in the order of D6_ORIENTATIONS
+        let triangles_per_face = indices.len() / 3 / 6;
+        for (i, triangle) in indices.chunks(3).enumerate() {
+            let sixth = (FaceLayout::WESTERN.values()[i / triangles_per_face] - 1) as f32 / 6.0;
+            for index in triangle {
+                let [u, v] = uvs[*index];
+                assert!(u >= sixth - 1e-5 && u <= sixth + 1.0 / 6.0 + 1e-5);
+                assert!((0.0..=1.0).contains(&v));
+            }
+        }
+    }
+
+    #[test]
+    fn rounded_d6_rejects_invalid_parameters() {
+        for (segments, radii) in [
+            (0, (0.05, 0.05)),
+            (33, (0.05, 0.05)),
+            (4, (-0.01, 0.05)),
+            (4, (0.1, 0.05)),
+            (4, (0.1, 0.31)),
+            (4, (f32::NAN, 0.1)),
+        ] {
+            assert!(matches!(
+                create_rounded_d6(segments, radii, 0.6, FaceLayout::WESTERN.values()),
+                Err(GeometryError::InvalidParameters(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn engraving_splits_long_edges() {
+        let cube = create_rounded_d6(1, (0.0, 0.0), 1.0, FaceLayout::WESTERN.values()).unwrap();
+        let engraved = engrave(&cube, 0.1, |_| 0.0).unwrap();
+        let (vertices, indices) = extract_mesh_attributes(&engraved).unwrap();
+        let mut area = 0.0;
+        for triangle in indices.chunks(3) {
+            for i in 0..3 {
+                let (a, b) = (triangle[i], triangle[(i + 1) % 3]);
+                let edge = Vec3::from_array(vertices[a]).distance(Vec3::from_array(vertices[b]));
+                assert!(edge <= 0.1, "{edge}");
+            }
+            area += triangle_normal(&vertices, triangle).length() / 2.0;
+        }
+        assert!((area - 6.0).abs() < 1e-4, "{area}");
+    }
+
+    #[test]
+    fn engraving_carves_along_the_normals() {
+        let cube = create_rounded_d6(1, (0.0, 0.0), 1.0, FaceLayout::WESTERN.values()).unwrap();
+        // a pit in the middle of the left side, which shows 2
+        let center = Vec2::new(1.5 / 6.0, 0.5);
+        let engraved = engrave(&cube, 0.05, |uv| {
+            if uv.distance(center) < 0.02 { 0.1 } else { 0.0 }
+        })
+        .unwrap();
+        let (vertices, _) = extract_mesh_attributes(&engraved).unwrap();
+        let inside = |[x, y, z]: &Vertex| [x, y, z].iter().all(|c| c.abs() <= 0.5 + 1e-6);
+        let carved = |[x, y, z]: &Vertex| (x + 0.4).abs() < 1e-6 && y.abs() < 0.1 && z.abs() < 0.1;
+        assert!(vertices.iter().all(inside));
+        assert!(vertices.iter().any(carved));
+    }
+
+    #[test]
+    fn engraving_needs_normals_and_uvs() {
+        let sphere = create_icosphere(1).unwrap();
+        assert!(matches!(
+            engrave(&sphere, 0.1, |_| 0.0),
+            Err(GeometryError::MissingAttribute("normals"))
+        ));
+        let cube = create_rounded_d6(1, (0.0, 0.0), 1.0, FaceLayout::WESTERN.values()).unwrap();
+        assert!(matches!(
+            engrave(&cube, 0.0, |_| 0.0),
+            Err(GeometryError::InvalidParameters(_))
+        ));
+    }
+
+    #[test]
+    fn d6_rejects_invalid_parameters() {
+        for (depth, threshold, size, faces) in [
+            (9, 0.72, 0.6, FaceLayout::WESTERN.values()),
+            (4, 0.5, 0.6, FaceLayout::WESTERN.values()),
+            (4, 1.0, 0.6, FaceLayout::WESTERN.values()),
+            (4, f32::NAN, 0.6, FaceLayout::WESTERN.values()),
+            (4, 0.72, 0.0, FaceLayout::WESTERN.values()),
+            (4, 0.72, f32::INFINITY, FaceLayout::WESTERN.values()),
+            (4, 0.72, 0.6, [0, 5, 6, 1, 4, 3]),
+            (4, 0.72, 0.6, [7, 5, 6, 1, 4, 3]),
+        ] {
+            assert!(matches!(
+                create_d6(depth, threshold, size, faces),
+                Err(GeometryError::InvalidParameters(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use bevy::prelude::*;
+use std::fmt::{Display, Formatter};
+
+// left, right, up, down, front, back; opposite faces are next to each other
+pub const AXES: [Vec3; 6] = [
+    Vec3::NEG_X,
+    Vec3::X,
+    Vec3::Y,
+    Vec3::NEG_Y,
+    Vec3::Z,
+    Vec3::NEG_Z,
+];
+
+#[derive(Component, Clone, Copy, PartialEq, Debug)]
+pub struct FaceLayout {
+    values: [u8; 6],
+}
+
+// the layout new dice get, changing it rebuilds the meshes as the uvs follow the layout
+#[derive(Resource)]
+pub struct SelectedLayout(pub FaceLayout);
+
+#[derive(Debug, PartialEq)]
+pub enum LayoutError {
+    OutOfRange(u8),
+    Duplicate(u8),
+    // the two faces and the sum they should have
+    OppositeSum(u8, u8, u8),
+}
+
+impl Display for LayoutError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LayoutError::OutOfRange(value) => write!(f, "face {value} is not between 1 and 6"),
+            LayoutError::Duplicate(value) => write!(f, "face {value} is used more than once"),
+            LayoutError::OppositeSum(lhs, rhs, sum) => {
+                write!(f, "opposite faces {lhs} and {rhs} do not sum up to {sum}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+impl FaceLayout {
+    // 1, 2 and 3 are arranged counterclockwise around their shared corner
+    pub const WESTERN: FaceLayout = FaceLayout {
+        values: [2, 5, 6, 1, 4, 3],
+    };
+    // the mirror image of the western layout, 1, 2 and 3 are arranged clockwise
+    pub const ASIAN: FaceLayout = FaceLayout {
+        values: [5, 2, 6, 1, 4, 3],
+    };
+    pub const PRESETS: [FaceLayout; 2] = [FaceLayout::WESTERN, FaceLayout::ASIAN];
+
+    pub fn name(&self) -> &'static str {
+        if *self == FaceLayout::WESTERN {
+            "Western"
+        } else if *self == FaceLayout::ASIAN {
+            "Asian"
+        } else {
+            "Custom"
+        }
+    }
+
+    pub fn custom(values: [u8; 6], opposite_sum: bool) -> Result<Self, LayoutError> {
+        for (i, value) in values.iter().enumerate() {
+            if !(1..=6).contains(value) {
+                return Err(LayoutError::OutOfRange(*value));
+            }
+            if values[..i].contains(value) {
+                return Err(LayoutError::Duplicate(*value));
+            }
+        }
+        if opposite_sum {
+            // AXES lists opposite faces next to each other, every such pair has the same sum
+            let sum = values.len() as u8 + 1;
+            for pair in values.chunks(2) {
+                if pair[0] + pair[1] != sum {
+                    return Err(LayoutError::OppositeSum(pair[0], pair[1], sum));
+                }
+            }
+        }
+        Ok(FaceLayout { values })
+    }
+
+    // western, asian or six comma separated values in the order of AXES, followed by ! if opposite
+    // faces have to sum up like on the usual dice
+    pub fn parse(value: &str) -> Result<Self, String> {
+        if let Some(preset) = FaceLayout::PRESETS
+            .into_iter()
+            .find(|preset| preset.name().eq_ignore_ascii_case(value))
+        {
+            return Ok(preset);
+        }
+        let (value, opposite_sum) = match value.strip_suffix('!') {
+            Some(value) => (value, true),
+            None => (value, false),
+        };
+        let values = value
+            .split(',')
+            .map(|face| face.trim().parse::<u8>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| error.to_string())?;
+        let values = <[u8; 6]>::try_from(values)
+            .map_err(|_| String::from("a layout needs exactly six faces"))?;
+        FaceLayout::custom(values, opposite_sum).map_err(|error| error.to_string())
+    }
+
+    pub fn values(&self) -> [u8; 6] {
+        self.values
+    }
+
+    pub fn normal(&self, face: u8) -> Option<Vec3> {
+        self.values
+            .iter()
+            .position(|value| *value == face)
+            .map(|index| AXES[index])
+    }
+
+    pub fn top_face(&self, rotation: Quat) -> u8 {
+        let (index, _) = AXES
+            .iter()
+            .enumerate()
+            .max_by(|(_, lhs), (_, rhs)| {
+                (rotation * **lhs)
+                    .dot(Vec3::Y)
+                    .total_cmp(&(rotation * **rhs).dot(Vec3::Y))
+            })
+            .expect("axes");
+        self.values[index]
+    }
+}
+
+impl Default for FaceLayout {
+    fn default() -> Self {
+        FaceLayout::WESTERN
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layouts_are_parsed_and_checked() {
+        assert_eq!(FaceLayout::parse("2,5,6,1,4,3!"), Ok(FaceLayout::WESTERN));
+        assert!(FaceLayout::parse("1,2,3,4,5,6").is_ok());
+        assert_eq!(
+            FaceLayout::parse("1,2,3,4,5,6!"),
+            Err(String::from("opposite faces 1 and 2 do not sum up to 7"))
+        );
+        assert!(FaceLayout::parse("1,2,3,4,5,7").is_err());
+        assert!(FaceLayout::parse("1,2,3,4,5").is_err());
+        for preset in FaceLayout::PRESETS {
+            assert_eq!(FaceLayout::parse(&preset.name().to_lowercase()), Ok(preset));
+        }
+        assert_eq!(FaceLayout::parse("1,6,2,5,3,4").unwrap().name(), "Custom");
+    }
+
+    #[test]
+    fn broken_rotations_still_read_a_face() {
+        let layout = FaceLayout::WESTERN;
+        assert_eq!(layout.top_face(Quat::IDENTITY), 6);
+        let face = layout.top_face(Quat::from_xyzw(f32::NAN, 0.0, 0.0, 1.0));
+        assert!((1..=6).contains(&face));
+    }
+}
@@ -0,0 +1,405 @@
+use crate::die::Die;
+use crate::faces::DieFaces;
+use crate::game::{GameMode, Seats, clear_for_deal, shake_dice};
+use crate::players::{Owner, Players};
+use crate::{Concealed, CountDie, Counted, Cup, DieSpawner, Roll};
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{EguiContextPass, EguiContexts, egui};
+
+// the bluffing game with two dice under a cup, also known as Meiern
+pub struct MaexchenPlugin;
+
+// two dice read as a number with the higher die first
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Throw {
+    high: u8,
+    low: u8,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Phase {
+    // two dice wait in the active cup
+    Shaking,
+    Settling,
+    // only the thrower has seen the dice and announces a throw, true or not
+    Announcing,
+    // the next player believes the announcement and throws again, or lifts the cup
+    Deciding,
+    Revealed { loser: usize },
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Action {
+    Shake,
+    Announce(Throw),
+    Believe,
+    Lift,
+    NextRound,
+}
+
+#[derive(Resource)]
+pub struct Maexchen {
+    phase: Phase,
+    // the announcement that has to be beaten, and who made it
+    announced: Option<(usize, Throw)>,
+    thrown: Option<Throw>,
+    // clicked in the panel, carried out by play_maexchen
+    action: Option<Action>,
+    // the active player gets two new dice in their cup
+    deal: bool,
+    outcome: String,
+    // the round starts over when somebody joins, leaves or moves
+    seats: Seats,
+}
+
+impl Plugin for MaexchenPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                start_maexchen.run_if(resource_changed::<GameMode>),
+                (play_maexchen, deal_dice).run_if(resource_exists::<Maexchen>),
+            )
+                .chain(),
+        )
+        .add_systems(
+            EguiContextPass,
+            maexchen_panel.run_if(resource_exists::<Maexchen>),
+        );
+    }
+}
+
+impl Throw {
+    pub fn new(a: u8, b: u8) -> Self {
+        Throw {
+            high: a.max(b),
+            low: a.min(b),
+        }
+    }
+
+    pub fn is_maexchen(&self) -> bool {
+        (self.high, self.low) == (2, 1)
+    }
+
+    // plain throws rank by their number, doubles above them and Mäxchen above everything
+    fn rank(&self) -> u8 {
+        if self.is_maexchen() {
+            200
+        } else if self.high == self.low {
+            100 + self.high
+        } else {
+            self.high * 10 + self.low
+        }
+    }
+
+    pub fn beats(&self, other: &Throw) -> bool {
+        self.rank() > other.rank()
+    }
+
+    // from the lowest to the highest
+    pub fn all() -> Vec<Throw> {
+        let mut throws = (1..=6)
+            .flat_map(|high| (1..=high).map(move |low| Throw::new(high, low)))
+            .collect::<Vec<_>>();
+        throws.sort_by_key(Throw::rank);
+        throws
+    }
+
+    pub fn name(&self) -> String {
+        if self.is_maexchen() {
+            String::from("21 Mäxchen")
+        } else if self.high == self.low {
+            format!("{}{} double", self.high, self.low)
+        } else {
+            format!("{}{}", self.high, self.low)
+        }
+    }
+}
+
+// whoever lifts the cup loses if the announcement was true, the announcer if it was too high,
+// and a Mäxchen costs twice
+fn judge(announced: Throw, thrown: Throw) -> (bool, i32) {
+    let lied = announced.beats(&thrown);
+    (lied, if announced.is_maexchen() { 2 } else { 1 })
+}
+
+impl Maexchen {
+    fn new(players: &Players) -> Self {
+        Maexchen {
+            phase: Phase::Shaking,
+            announced: None,
+            thrown: None,
+            action: None,
+            deal: true,
+            outcome: String::new(),
+            seats: Seats::new(players),
+        }
+    }
+}
+
+fn start_maexchen(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    players: Res<Players>,
+    game: Option<Res<Maexchen>>,
+    concealed: Query<Entity, With<Concealed>>,
+) {
+    if *mode == GameMode::Maexchen {
+        commands.insert_resource(Maexchen::new(&players));
+    } else if game.is_some() {
+        commands.remove_resource::<Maexchen>();
+        for entity in concealed.iter() {
+            commands.entity(entity).remove::<Concealed>();
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn play_maexchen(
+    mut commands: Commands,
+    mut game: ResMut<Maexchen>,
+    mut players: ResMut<Players>,
+    mut count_die: ResMut<CountDie>,
+    roll: Single<&Roll>,
+    mut dice: Query<
+        (
+            Entity,
+            &Owner,
+            &mut LinearVelocity,
+            &mut AngularVelocity,
+            Has<Counted>,
+        ),
+        With<Die>,
+    >,
+    concealed: Query<Entity, With<Concealed>>,
+) {
+    // the announcement would point at the wrong player otherwise
+    if game.seats.changed(&players) {
+        *game = Maexchen::new(&players);
+        count_die.0 = false;
+        return;
+    }
+    let active = players.active;
+    let own = dice.iter().filter(|die| die.1.0 == active).count();
+    // a die fell off the table or was cleared, so the throw cannot be read
+    if game.phase == Phase::Settling && own != 2 {
+        game.phase = Phase::Shaking;
+        game.deal = true;
+        count_die.0 = false;
+    }
+    if game.phase == Phase::Settling
+        && own == 2
+        && dice.iter().filter(|die| die.1.0 == active).all(|die| die.4)
+    {
+        let values = roll
+            .results
+            .iter()
+            .filter(|(_, owner, _)| *owner == active)
+            .filter_map(|(_, _, label)| label.value)
+            .collect::<Vec<_>>();
+        if let [a, b] = values[..] {
+            game.thrown = Some(Throw::new(a as u8, b as u8));
+            game.phase = Phase::Announcing;
+            count_die.0 = false;
+        }
+    }
+
+    let Some(action) = game.action.take() else {
+        return;
+    };
+    match (game.phase, action) {
+        // dice that were cleared or added are dealt again
+        (Phase::Shaking, Action::Shake) if own != 2 => game.deal = true,
+        (Phase::Shaking, Action::Shake) => {
+            let shaken = dice.iter_mut().filter(|die| die.1.0 == active).map(
+                |(entity, _, linear_velocity, angular_velocity, _)| {
+                    (entity, linear_velocity, angular_velocity)
+                },
+            );
+            shake_dice(&mut commands, shaken);
+            count_die.0 = true;
+            game.phase = Phase::Settling;
+        }
+        (Phase::Announcing, Action::Announce(throw))
+            if game
+                .announced
+                .is_none_or(|(_, announced)| throw.beats(&announced)) =>
+        {
+            game.announced = Some((active, throw));
+            game.phase = Phase::Deciding;
+            players.next();
+        }
+        // nothing beats a Mäxchen, so it can only be checked
+        (Phase::Deciding, Action::Believe)
+            if game
+                .announced
+                .is_some_and(|(_, announced)| !announced.is_maexchen()) =>
+        {
+            game.thrown = None;
+            game.phase = Phase::Shaking;
+            game.deal = true;
+        }
+        (Phase::Deciding, Action::Lift) => {
+            let (Some((announcer, announced)), Some(thrown)) = (game.announced, game.thrown) else {
+                return;
+            };
+            let (lied, points) = judge(announced, thrown);
+            let loser = if lied { announcer } else { active };
+            players.list[loser].score -= points;
+            game.outcome = format!(
+                "{} announced {} and threw {}, {} loses {points}",
+                players.list[announcer].name,
+                announced.name(),
+                thrown.name(),
+                players.list[loser].name,
+            );
+            for entity in concealed.iter() {
+                commands.entity(entity).remove::<Concealed>();
+            }
+            game.phase = Phase::Revealed { loser };
+        }
+        // the loser starts the next round
+        (Phase::Revealed { loser }, Action::NextRound) => {
+            players.active = loser.min(players.list.len() - 1);
+            game.announced = None;
+            game.thrown = None;
+            game.phase = Phase::Shaking;
+            game.deal = true;
+        }
+        _ => {}
+    }
+}
+
+fn deal_dice(
+    mut game: ResMut<Maexchen>,
+    mut spawner: DieSpawner,
+    mut roll: Single<&mut Roll>,
+    dice: Query<Entity, With<Die>>,
+    cups: Query<(&Owner, &Transform), With<Cup>>,
+) {
+    if !game.deal || !clear_for_deal(&mut spawner, &mut roll, &dice, cups.iter().len()) {
+        return;
+    }
+    game.deal = false;
+    let Some((_, cup)) = cups
+        .iter()
+        .find(|(owner, _)| owner.0 == spawner.players.active)
+    else {
+        return;
+    };
+    for x in [-0.4, 0.4] {
+        let transform = Transform::from_translation(cup.translation + Vec3::new(x, 1.6, 0.0));
+        if let Some(entity) = spawner.spawn_with(transform, DieFaces::numbered()) {
+            spawner.commands.entity(entity).insert(Concealed);
+        }
+    }
+}
+
+fn maexchen_panel(
+    mut contexts: EguiContexts,
+    mut game: ResMut<Maexchen>,
+    players: Res<Players>,
+    mut choice: Local<Option<Throw>>,
+) {
+    let active = players.active().name.as_str();
+    let announced = game.announced;
+    let mut action = None;
+    egui::Window::new("Mäxchen").show(contexts.ctx_mut(), |ui| {
+        if let Some((announcer, throw)) = announced
+            && let Some(announcer) = players.list.get(announcer)
+        {
+            ui.label(format!("{} announced {}", announcer.name, throw.name()));
+        }
+        match game.phase {
+            Phase::Shaking => {
+                ui.label(format!("{active}, shake the cup"));
+                if ui.button("Shake").clicked() {
+                    action = Some(Action::Shake);
+                }
+            }
+            Phase::Settling => {
+                ui.label("the dice are settling");
+            }
+            Phase::Announcing => {
+                ui.label(format!("{active}, hold P to peek under the cup"));
+                let throws = Throw::all()
+                    .into_iter()
+                    .filter(|throw| announced.is_none_or(|(_, announced)| throw.beats(&announced)))
+                    .collect::<Vec<_>>();
+                if choice.is_none_or(|choice| !throws.contains(&choice)) {
+                    *choice = throws.first().copied();
+                }
+                egui::ComboBox::from_label("Throw")
+                    .selected_text(choice.map(|throw| throw.name()).unwrap_or_default())
+                    .show_ui(ui, |ui| {
+                        for throw in throws {
+                            ui.selectable_value(&mut *choice, Some(throw), throw.name());
+                        }
+                    });
+                if let Some(throw) = *choice
+                    && ui.button("Announce").clicked()
+                {
+                    action = Some(Action::Announce(throw));
+                }
+            }
+            Phase::Deciding => {
+                ui.label(format!(
+                    "{active}, believe it and throw again, or lift the cup"
+                ));
+                ui.horizontal(|ui| {
+                    let believable = announced.is_some_and(|(_, throw)| !throw.is_maexchen());
+                    if ui
+                        .add_enabled(believable, egui::Button::new("Believe"))
+                        .clicked()
+                    {
+                        action = Some(Action::Believe);
+                    }
+                    if ui.button("Lift the cup").clicked() {
+                        action = Some(Action::Lift);
+                    }
+                });
+            }
+            Phase::Revealed { .. } => {
+                ui.label(game.outcome.as_str());
+                if ui.button("Next round").clicked() {
+                    action = Some(Action::NextRound);
+                }
+            }
+        }
+    });
+    if action.is_some() {
+        game.action = action;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn throws_are_ranked() {
+        let throws = Throw::all();
+        assert_eq!(throws.len(), 21);
+        let names = throws.iter().map(Throw::name).collect::<Vec<_>>();
+        assert_eq!(names[..3], ["31", "32", "41"]);
+        assert_eq!(names[13], "65");
+        assert_eq!(names[14], "11 double");
+        assert_eq!(names[20], "21 Mäxchen");
+        for pair in throws.windows(2) {
+            assert!(pair[1].beats(&pair[0]), "{:?}", pair);
+            assert!(!pair[0].beats(&pair[1]), "{:?}", pair);
+        }
+        assert_eq!(Throw::new(1, 3), Throw::new(3, 1));
+    }
+
+    #[test]
+    fn lifting_the_cup_finds_the_liar() {
+        let (announced, thrown) = (Throw::new(5, 4), Throw::new(6, 1));
+        assert_eq!(judge(announced, thrown), (false, 1));
+        assert_eq!(judge(announced, announced), (false, 1));
+        assert_eq!(judge(Throw::new(3, 3), thrown), (true, 1));
+        assert_eq!(judge(Throw::new(2, 1), Throw::new(6, 6)), (true, 2));
+        assert_eq!(judge(Throw::new(1, 2), Throw::new(2, 1)), (false, 2));
+    }
+}
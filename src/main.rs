@@ -1,22 +1,77 @@
+mod atlas;
+mod cache;
+mod cli;
+mod die;
+mod export;
+mod faces;
+mod farkle;
+mod game;
 mod geometry;
+mod layout;
+mod maexchen;
+mod net;
+mod perudo;
+mod players;
+mod protocol;
+mod session;
+mod settle;
+mod stats;
+mod style;
+mod tuning;
+mod validation;
 
-use crate::geometry::create_d6;
+use crate::atlas::{AtlasSettings, Marking, SelectedMarking};
+use crate::cache::{Cache, CacheKey};
+use crate::cli::{has_flag, value_of};
+use crate::die::{
+    ColliderMode, D6_LODS, Die, DieShape, Engraved, SelectedShape, cached_d6, cached_engraved_d6,
+};
+use crate::export::ExportConfig;
+use crate::faces::{DieFaces, FaceLabel, SelectedFaces, describe};
+use crate::farkle::FarklePlugin;
+use crate::game::GameMode;
+use crate::geometry::GeometryError;
+use crate::layout::{FaceLayout, SelectedLayout};
+use crate::maexchen::MaexchenPlugin;
+use crate::net::{ClientPlugin, HostPlugin, NetConfig};
+use crate::perudo::PerudoPlugin;
+use crate::players::{ActiveCup, Owner, Player, Players};
+use crate::session::{SessionFile, players_panel, save_session};
+use crate::settle::{AutoSleep, SettleMode, SettlePlugin, SettleSettings, settle_panel};
+use crate::stats::StatsConfig;
+use crate::style::{
+    DieAtlas, DieMaterials, Highlighted, evict_die_materials, style_dice, style_picker,
+};
+use crate::tuning::{PhysicsTuning, TuningPresets, apply_tuning, tuning_panel};
 use avian3d::math::Vector;
 use avian3d::prelude::*;
+use bevy::app::ScheduleRunnerPlugin;
 use bevy::color::palettes::css::{ORANGE, RED};
-use bevy::image::ImageLoaderSettings;
+use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
+use bevy::ecs::system::SystemParam;
 use bevy::input::common_conditions::{input_just_pressed, input_toggle_active};
 use bevy::pbr::PointLightShadowMap;
 use bevy::prelude::*;
-use bevy_inspector_egui::bevy_egui::EguiPlugin;
+use bevy::render::RenderPlugin;
+use bevy::render::settings::WgpuSettings;
+use bevy::scene::SceneInstanceReady;
+use bevy::window::ExitCondition;
+use bevy::winit::WinitPlugin;
+use bevy_inspector_egui::bevy_egui::{EguiContextPass, EguiPlugin};
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use rand::Rng;
+use std::collections::HashMap;
+use std::time::Duration;
 
+// units per second the cup is raised or lowered with the mouse buttons
+const CUP_LIFT_SPEED: f32 = 6.25;
+
+// angular velocity in radians per second, applied while spinning is toggled on
 #[derive(Component)]
 struct Spinnable(Vec3);
 
-#[derive(Component)]
-struct Die;
+#[derive(Resource, Default)]
+struct Spinning(bool);
 
 #[derive(Component)]
 struct Cup;
@@ -27,39 +82,247 @@ struct Ground;
 #[derive(Component)]
 struct Counted;
 
+#[derive(Component)]
+struct CupCollider;
+
+// a die under the cup that only its owner may peek at
+#[derive(Component)]
+struct Concealed;
+
 #[derive(Component)]
 struct Cursor;
 
 #[derive(Resource)]
 struct DebugRenderEnabled(bool);
 
-#[derive(Component, Default)]
-struct AutoSleep {
-    translation: Vec3,
-    rotation: Vec3,
-    time: f32,
-}
-
 #[derive(Component)]
 struct Roll {
-    faces: Vec<u8>,
+    // the die each result was read from, so that it can be taken back when the die is disturbed,
+    // and the player who owns it
+    results: Vec<(Entity, usize, FaceLabel)>,
 }
 
 #[derive(Resource)]
 struct CountDie(bool);
 
+// what the active cup is asked to do, by the mouse or by a remote player
+#[derive(Resource, Default)]
+struct CupInput {
+    // the point on the table the cup moves above
+    target: Option<Vec3>,
+    // 1 raises the cup, -1 lowers it
+    lift: f32,
+    tilt: bool,
+}
+
+// dice --bench <count> pours count dice into the cup and logs frame times
+#[derive(Resource)]
+struct Bench {
+    remaining: usize,
+    timer: Timer,
+}
+
 #[derive(Resource)]
 struct D6 {
-    mesh: Handle<Mesh>,
+    layout: FaceLayout,
+    collider_mode: ColliderMode,
+    shapes: HashMap<CacheKey, D6Shape>,
+    engraved: HashMap<CacheKey, Handle<Mesh>>,
+    atlases: HashMap<(String, Marking), DieAtlas>,
+}
+
+#[derive(Clone)]
+struct D6Shape {
+    lods: Lods,
     collider: Collider,
-    color_texture: Handle<Image>,
-    depth_texture: Handle<Image>,
-    normal_texture: Handle<Image>,
+}
+
+// camera distance from which each mesh is shown, in ascending order
+#[derive(Component, Clone)]
+struct Lods(Vec<(f32, Handle<Mesh>)>);
+
+#[derive(SystemParam)]
+struct DieSpawner<'w, 's> {
+    commands: Commands<'w, 's>,
+    d6: ResMut<'w, D6>,
+    meshes: ResMut<'w, Assets<Mesh>>,
+    images: ResMut<'w, Assets<Image>>,
+    cache: Res<'w, Cache>,
+    faces: Res<'w, SelectedFaces>,
+    marking: Res<'w, SelectedMarking>,
+    shape: Res<'w, SelectedShape>,
+    engraved: Res<'w, Engraved>,
+    tuning: Res<'w, PhysicsTuning>,
+    players: Res<'w, Players>,
+}
+
+impl D6 {
+    fn shape(
+        &mut self,
+        shape: &DieShape,
+        cache: &Cache,
+        meshes: &mut Assets<Mesh>,
+    ) -> Result<D6Shape, GeometryError> {
+        if let Some(d6_shape) = self.shapes.get(&shape.key()) {
+            return Ok(d6_shape.clone());
+        }
+        let (lods, collider) = cached_d6(cache, &self.layout, shape, self.collider_mode)?;
+        let lods = D6_LODS
+            .iter()
+            .zip(lods)
+            .map(|((_, distance), mesh)| (*distance, meshes.add(mesh)))
+            .collect();
+        let d6_shape = D6Shape {
+            lods: Lods(lods),
+            collider,
+        };
+        self.shapes.insert(shape.key(), d6_shape.clone());
+        Ok(d6_shape)
+    }
+
+    // the engraving depends on the labels as well, so it is kept apart from the shapes
+    fn engraved(
+        &mut self,
+        shape: &DieShape,
+        faces: &DieFaces,
+        marking: Marking,
+        cache: &Cache,
+        meshes: &mut Assets<Mesh>,
+    ) -> Result<Handle<Mesh>, GeometryError> {
+        let key = shape
+            .key()
+            .bytes(faces.name.as_bytes())
+            .bytes(marking.name().as_bytes());
+        if let Some(mesh) = self.engraved.get(&key) {
+            return Ok(mesh.clone());
+        }
+        let mesh = cached_engraved_d6(
+            cache,
+            &self.layout,
+            shape,
+            &AtlasSettings {
+                marking,
+                ..default()
+            },
+            &faces.texts(),
+        )?;
+        let mesh = meshes.add(mesh);
+        self.engraved.insert(key, mesh.clone());
+        Ok(mesh)
+    }
+
+    fn atlas(
+        &mut self,
+        faces: &DieFaces,
+        marking: Marking,
+        images: &mut Assets<Image>,
+    ) -> DieAtlas {
+        self.atlases
+            .entry((faces.name.clone(), marking))
+            .or_insert_with(|| {
+                let atlas = AtlasSettings {
+                    marking,
+                    ..default()
+                }
+                .generate(&faces.texts());
+                DieAtlas {
+                    color: images.add(atlas.color),
+                    depth: images.add(atlas.depth),
+                    normal: images.add(atlas.normal),
+                    parallax_depth_scale: atlas.parallax_depth_scale,
+                    engraved: false,
+                }
+            })
+            .clone()
+    }
+}
+
+// the meshes were built with the uvs of the previous layout, and the atlases go along with them
+fn apply_layout(layout: Res<SelectedLayout>, mut d6: ResMut<D6>) {
+    if d6.layout == layout.0 {
+        return;
+    }
+    d6.layout = layout.0;
+    d6.shapes.clear();
+    d6.engraved.clear();
+    d6.atlases.clear();
 }
 
 fn main() {
-    App::new()
-        .add_plugins((
+    let args = std::env::args().collect::<Vec<_>>();
+    if let Some(config) = StatsConfig::from_args(&args) {
+        stats::run(config).expect("stats");
+        return;
+    }
+    if let Some(config) = ExportConfig::from_args(&args) {
+        export::run(config).expect("export");
+        return;
+    }
+    let collider_mode = value_of(&args, "--collider")
+        .map(|value| ColliderMode::parse(value).expect("--collider"))
+        .unwrap_or_default();
+    let engraved = Engraved(has_flag(&args, "--engrave"));
+    let layout = value_of(&args, "--layout")
+        .map(|value| FaceLayout::parse(value).expect("--layout"))
+        .unwrap_or_default();
+    let faces = value_of(&args, "--faces")
+        .map(|value| DieFaces::parse(value).expect("--faces"))
+        .unwrap_or_else(DieFaces::numbered);
+    let marking = value_of(&args, "--marking")
+        .map(|value| Marking::parse(value).expect("--marking"))
+        .unwrap_or_default();
+    let settle_mode = value_of(&args, "--settle")
+        .map(|value| SettleMode::parse(value).expect("--settle"))
+        .unwrap_or_default();
+    let game_mode = value_of(&args, "--game")
+        .map(|value| GameMode::parse(value).expect("--game"))
+        .unwrap_or_default();
+    let net = NetConfig::from_args(&args);
+    let game_mode = match &net {
+        Some(net) => net.check_game(game_mode).expect("--game"),
+        None => game_mode,
+    };
+    let mut session = SessionFile::from_env();
+    // --players starts a new session instead of continuing the saved one, clients get theirs from the host
+    let players = match (value_of(&args, "--players"), &net) {
+        (_, Some(NetConfig::Join { .. })) => Players::new(1).expect("a single player"),
+        (Some(value), _) => Players::new(
+            value
+                .parse()
+                .expect("--players expects a number of players"),
+        )
+        .expect("--players"),
+        (None, _) => session
+            .load()
+            .unwrap_or_else(|| Players::new(1).expect("a single player")),
+    };
+    let bench = value_of(&args, "--bench")
+        .map(|value| value.parse().expect("--bench expects a number of dice"));
+    let headless = matches!(net, Some(NetConfig::Host { headless: true, .. }));
+    let client = matches!(net, Some(NetConfig::Join { .. }));
+    let mut app = App::new();
+    if headless {
+        // no window and no gpu, the table is only simulated and sent to the clients
+        app.add_plugins((
+            DefaultPlugins
+                .set(WindowPlugin {
+                    primary_window: None,
+                    exit_condition: ExitCondition::DontExit,
+                    ..default()
+                })
+                .set(RenderPlugin {
+                    render_creation: WgpuSettings {
+                        backends: None,
+                        ..default()
+                    }
+                    .into(),
+                    ..default()
+                })
+                .disable::<WinitPlugin>(),
+            ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(1.0 / 60.0)),
+        ));
+    } else {
+        app.add_plugins((
             DefaultPlugins.set(WindowPlugin {
                 primary_window: Some(Window {
                     title: String::from("Dice"),
@@ -67,56 +330,145 @@ fn main() {
                 }),
                 ..default()
             }),
-            PhysicsPlugins::default(),
-            PhysicsDebugPlugin::default(),
             EguiPlugin {
                 enable_multipass_for_primary_context: true,
             },
             WorldInspectorPlugin::default().run_if(input_toggle_active(false, KeyCode::Escape)),
+        ));
+    }
+    app.add_plugins((
+        PhysicsPlugins::default(),
+        PhysicsDebugPlugin::default(),
+        SettlePlugin,
+    ))
+    .insert_gizmo_config(
+        PhysicsGizmos::default(),
+        GizmoConfig {
+            enabled: false,
+            ..default()
+        },
+    )
+    .insert_resource(DebugRenderEnabled(false))
+    .insert_resource(CountDie(false))
+    .insert_resource(CupInput::default())
+    .insert_resource(Spinning::default())
+    .insert_resource(PhysicsTuning::default())
+    .insert_resource(TuningPresets::from_env())
+    .insert_resource(players)
+    .insert_resource(session)
+    .insert_resource(game_mode)
+    .insert_resource(SelectedFaces(faces))
+    .insert_resource(SelectedMarking(marking))
+    .insert_resource(SelectedLayout(layout))
+    .insert_resource(SelectedShape(DieShape::default()))
+    .insert_resource(engraved)
+    .insert_resource(DieMaterials::default())
+    .insert_resource(Cache::from_env())
+    .insert_resource(collider_mode)
+    .insert_resource(SettleSettings {
+        mode: settle_mode,
+        ..default()
+    })
+    .insert_resource(PointLightShadowMap { size: 2048 })
+    .add_systems(Startup, setup)
+    .add_systems(
+        Update,
+        (
+            read_cup_input,
+            (follow_turn, seat_players)
+                .chain()
+                .run_if(resource_changed::<Players>),
+            position_cursor,
+            highlight_selected_die,
+            conceal_dice,
+            select_lod,
+            apply_tuning.run_if(resource_changed::<PhysicsTuning>),
+            apply_layout.run_if(resource_changed::<SelectedLayout>),
+            toggle_debug_render.run_if(input_just_pressed(KeyCode::Escape)),
+        ),
+    )
+    .add_observer(prepare_cup)
+    .add_systems(
+        PostUpdate,
+        (
+            (style_dice, evict_die_materials).chain(),
+            despawn_fallen_dice,
+        ),
+    );
+    match net {
+        Some(NetConfig::Host { address, headless }) => {
+            app.add_plugins(HostPlugin { address, headless });
+        }
+        Some(NetConfig::Join { address, name }) => {
+            app.add_plugins(ClientPlugin { address, name });
+        }
+        None => {}
+    }
+    // clients only show the table of the host
+    if !client {
+        app.add_plugins((MaexchenPlugin, PerudoPlugin, FarklePlugin))
+            .add_systems(Startup, spawn_cube.after(setup))
+            .add_systems(
+                Update,
+                (
+                    (
+                        uncount_disturbed_dice,
+                        count_faces,
+                        resolve_roll.run_if(resource_equals(GameMode::Free)),
+                        show_roll,
+                    )
+                        .chain(),
+                    save_session.run_if(resource_changed::<Players>),
+                    toggle_spin.run_if(input_just_pressed(KeyCode::Space)),
+                    // the games deal the dice and pass the turn themselves
+                    (
+                        next_turn.run_if(input_just_pressed(KeyCode::Tab)),
+                        spawn_cube.run_if(input_just_pressed(KeyCode::Enter)),
+                    )
+                        .run_if(resource_equals(GameMode::Free)),
+                ),
+            )
+            // everything that pushes bodies runs in step with the physics, independent of the frame rate
+            .add_systems(
+                FixedUpdate,
+                (spin, move_active_cup, roll_cup_towards_center),
+            )
+            .add_systems(
+                PostUpdate,
+                clear_dice.run_if(
+                    input_just_pressed(KeyCode::Backspace).and(resource_equals(GameMode::Free)),
+                ),
+            );
+    }
+    if !client && !headless {
+        app.add_systems(
+            EguiContextPass,
+            (style_picker, players_panel, settle_panel, tuning_panel),
+        );
+    }
+    if let Some(remaining) = bench {
+        app.insert_resource(Bench {
+            remaining,
+            timer: Timer::from_seconds(0.1, TimerMode::Repeating),
+        })
+        .add_plugins((
+            FrameTimeDiagnosticsPlugin::default(),
+            LogDiagnosticsPlugin::default(),
         ))
-        .insert_gizmo_config(
-            PhysicsGizmos::default(),
-            GizmoConfig {
-                enabled: false,
-                ..default()
-            },
-        )
-        .insert_resource(DebugRenderEnabled(false))
-        .insert_resource(CountDie(false))
-        //.insert_resource(DeactivationTime(0.2))
-        .insert_resource(PointLightShadowMap { size: 2048 })
-        .add_systems(Startup, (setup, spawn_cube).chain())
         .add_systems(
             Update,
-            (
-                spin,
-                count_faces,
-                detect_sleep,
-                position_cursor,
-                move_cup_with_mouse,
-                highlight_selected_die,
-                roll_cup_towards_center,
-                spawn_cube.run_if(input_just_pressed(KeyCode::Enter)),
-                toggle_debug_render.run_if(input_just_pressed(KeyCode::Escape)),
-            ),
-        )
-        .add_systems(
-            PostUpdate,
-            (
-                // scene spawning happens between Update and PostUpdate
-                handle_asset_events,
-                despawn_fallen_dice,
-                clear_dice.run_if(input_just_pressed(KeyCode::Backspace)),
-            ),
-        )
-        .run();
+            pour_bench_dice.run_if(resource_exists::<Bench>.and(any_with_component::<CupCollider>)),
+        );
+    }
+    app.run();
 }
 
 fn setup(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    asset_server: Res<AssetServer>,
+    collider_mode: Res<ColliderMode>,
+    layout: Res<SelectedLayout>,
 ) {
     commands.spawn((
         Ground,
@@ -125,24 +477,12 @@ fn setup(
         Mesh3d(meshes.add(Cylinder::new(6.0, 0.2))),
         MeshMaterial3d(materials.add(Color::WHITE)),
     ));
-    let d6 = create_d6(4, 0.72, 0.6);
-    let collider = Collider::convex_decomposition_from_mesh_with_config(
-        &d6,
-        &VhacdParameters {
-            fill_mode: FillMode::SurfaceOnly,
-            ..default()
-        },
-    )
-    .expect("collider");
     commands.insert_resource(D6 {
-        mesh: meshes.add(d6),
-        collider,
-        color_texture: asset_server.load("d6.png"),
-        depth_texture: asset_server.load("d6_depth.png"),
-        normal_texture: asset_server
-            .load_with_settings("d6_normal.png", |settings: &mut ImageLoaderSettings| {
-                settings.is_srgb = false
-            }),
+        layout: layout.0,
+        collider_mode: *collider_mode,
+        shapes: HashMap::new(),
+        engraved: HashMap::new(),
+        atlases: HashMap::new(),
     });
     commands.spawn((
         PointLight {
@@ -153,19 +493,11 @@ fn setup(
         Transform::from_xyz(0.0, 10.0, 8.0),
     ));
     let camera_transform = Transform::from_xyz(-2.5, 7.0, 13.0).looking_at(Vec3::ZERO, Dir3::Y);
-    commands.spawn((Msaa::Sample8, Camera3d::default(), camera_transform.clone()));
+    commands.spawn((Msaa::Sample8, Camera3d::default(), camera_transform));
 
-    let cup = asset_server.load(GltfAssetLabel::Scene(0).from_asset("Cup mit col.glb"));
+    // the cups are placed by seat_players
     commands.spawn((
-        Cup,
-        SceneRoot(cup),
-        RigidBody::Kinematic,
-        Name::new("Cup"),
-        Transform::from_translation(Vec3::new(2.0, 1.2, 0.0)),
-    ));
-
-    commands.spawn((
-        Roll { faces: vec![] },
+        Roll { results: vec![] },
         Text::new("Roll:"),
         TextFont {
             font_size: 60.0,
@@ -186,213 +518,384 @@ fn setup(
     ));
 }
 
-fn spin(
-    mut query: Query<(&mut AngularVelocity, &Spinnable)>,
+// key presses are only reliable in Update, so the toggle is kept apart from the spinning itself
+fn toggle_spin(mut spinning: ResMut<Spinning>) {
+    spinning.0 = !spinning.0;
+}
+
+fn spin(spinning: Res<Spinning>, mut query: Query<(&mut AngularVelocity, &Spinnable)>) {
+    if !spinning.0 {
+        return;
+    }
+    for (mut angular_velocity, spinnable) in query.iter_mut() {
+        angular_velocity.0 = spinnable.0;
+    }
+}
+
+fn spawn_cube(mut spawner: DieSpawner) {
+    spawner.spawn(Transform::from_xyz(0.0, 4.0, 0.0));
+}
+
+// a few dice at a time, so that they do not spawn inside each other
+fn pour_bench_dice(
+    mut spawner: DieSpawner,
+    mut bench: ResMut<Bench>,
+    cup: Single<&Transform, (With<Cup>, With<ActiveCup>)>,
     time: Res<Time>,
-    keys: Res<ButtonInput<KeyCode>>,
-    mut enabled: Local<bool>,
 ) {
-    if keys.just_pressed(KeyCode::Space) {
-        *enabled = !*enabled;
-    }
-    for (mut rotation, spinnable) in query.iter_mut() {
-        if *enabled {
-            rotation.x = time.delta_secs() * spinnable.0.x;
-            rotation.y = time.delta_secs() * spinnable.0.y;
-            rotation.z = time.delta_secs() * spinnable.0.z;
+    if !bench.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let batch = bench.remaining.min(4);
+    for i in 0..batch {
+        let offset = Vec3::new((i % 2) as f32 - 0.5, 2.0, (i / 2) as f32 - 0.5) * 0.8;
+        spawner.spawn(Transform::from_translation(cup.translation + offset));
+    }
+    bench.remaining -= batch;
+    if bench.remaining == 0 {
+        spawner.commands.remove_resource::<Bench>();
+    }
+}
+
+impl Roll {
+    // a single player only sees the roll, otherwise every player gets a line and the active one a marker
+    fn describe(&self, players: &Players, concealed: impl Fn(Entity) -> bool) -> String {
+        let labels = |player: usize| {
+            self.results
+                .iter()
+                .filter(|(_, owner, _)| *owner == player)
+                .map(|(entity, _, label)| {
+                    if concealed(*entity) {
+                        FaceLabel::symbol("?", None)
+                    } else {
+                        label.clone()
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+        if players.list.len() == 1 {
+            return describe("Roll", &labels(0));
         }
+        players
+            .list
+            .iter()
+            .enumerate()
+            .map(|(i, player)| {
+                let marker = if i == players.active { "> " } else { "" };
+                format!("{marker}{}", describe(&player.name, &labels(i)))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
     }
 }
 
-fn spawn_cube(
-    mut commands: Commands,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    d6: Res<D6>,
-) {
-    let mut rng = rand::rng();
-    let angular_velocity = Vec3::new(
-        rng.random_range(-1.0..1.0),
-        rng.random_range(-1.0..1.0),
-        rng.random_range(-1.0..1.0),
-    );
-    let _color = Color::srgb(
-        rng.random_range(0.0..1.0),
-        rng.random_range(0.0..1.0),
-        rng.random_range(0.0..1.0),
-    );
-    let spin = Vec3::new(
-        rng.random_range(-1.0..1.0),
-        rng.random_range(-1.0..1.0),
-        rng.random_range(-1.0..1.0),
-    );
-    commands.spawn((
-        Die,
-        AutoSleep::default(),
-        Spinnable(spin * 800.0),
-        RigidBody::Dynamic,
-        GravityScale(20.0),
-        // this causes the dice to clip outside the cup, which looks awful
-        //TransformInterpolation,
-        Restitution::new(0.4),
-        AngularVelocity(angular_velocity * 8.0),
-        Mesh3d(d6.mesh.clone()),
-        MeshMaterial3d(materials.add(StandardMaterial {
-            normal_map_texture: Some(d6.normal_texture.clone()),
-            base_color_texture: Some(d6.color_texture.clone()),
-            depth_map: Some(d6.depth_texture.clone()),
-            parallax_depth_scale: 0.008,
-            perceptual_roughness: 0.8,
-            //base_color: color,
-            ..default()
-        })),
-        d6.collider.clone(),
-        Transform::from_xyz(0.0, 4.0, 0.0),
-    ));
+impl DieSpawner<'_, '_> {
+    // the material is assigned by style_dice, which shares it between dice with the same style
+    fn spawn(&mut self, transform: Transform) -> Option<Entity> {
+        let faces = self.faces.0.clone();
+        self.spawn_with(transform, faces)
+    }
+
+    // the faces are passed in for dice copied from a host, which may have chosen other faces;
+    // a shape that cannot be built, say from a bad settings file, is reported and no die spawned
+    fn spawn_with(&mut self, transform: Transform, faces: DieFaces) -> Option<Entity> {
+        self.build(transform, faces)
+            .inspect_err(|error| warn!("could not spawn a die: {error}"))
+            .ok()
+    }
+
+    fn build(&mut self, transform: Transform, faces: DieFaces) -> Result<Entity, GeometryError> {
+        let mut rng = rand::rng();
+        let angular_velocity = Vec3::new(
+            rng.random_range(-1.0..1.0),
+            rng.random_range(-1.0..1.0),
+            rng.random_range(-1.0..1.0),
+        );
+        let spin = Vec3::new(
+            rng.random_range(-1.0..1.0),
+            rng.random_range(-1.0..1.0),
+            rng.random_range(-1.0..1.0),
+        );
+        let mut atlas = self.d6.atlas(&faces, self.marking.0, &mut self.images);
+        let mut shape = self
+            .d6
+            .shape(&self.shape.0, &self.cache, &mut self.meshes)?;
+        if self.engraved.0 {
+            shape.lods.0[0].1 = self.d6.engraved(
+                &self.shape.0,
+                &faces,
+                self.marking.0,
+                &self.cache,
+                &mut self.meshes,
+            )?;
+            atlas.engraved = true;
+        }
+        let entity = Die::builder()
+            .transform(transform)
+            .angular_velocity(angular_velocity * self.tuning.spawn_angular_velocity)
+            .layout(self.d6.layout)
+            .spawn(&mut self.commands, shape.collider)
+            .insert((
+                self.tuning.die_components(),
+                Owner(self.players.active),
+                self.players.active().style,
+                faces,
+                atlas,
+                Highlighted::default(),
+                AutoSleep::default(),
+                Spinnable(spin * 12.5),
+                // this causes the dice to clip outside the cup, which looks awful
+                //TransformInterpolation,
+                Mesh3d(shape.lods.0[0].1.clone()),
+                shape.lods,
+                MeshMaterial3d::<StandardMaterial>::default(),
+            ))
+            .id();
+        Ok(entity)
+    }
 }
 
 fn clear_dice(
     mut commands: Commands,
-    mut roll: Single<(&mut Roll, &mut Text)>,
+    mut roll: Single<&mut Roll>,
     query: Query<Entity, With<Die>>,
     mut count_die: ResMut<CountDie>,
 ) {
     count_die.0 = false;
-    roll.0.faces.clear();
-    roll.1.0 = String::from("Roll:");
+    roll.results.clear();
     for entity in query.iter() {
         commands.entity(entity).despawn();
     }
 }
 
+#[allow(clippy::type_complexity)]
 fn count_faces(
     mut commands: Commands,
     count_die: Res<CountDie>,
-    mut roll: Single<(&mut Roll, &mut Text)>,
-    query: Query<(Entity, &Transform), (With<Die>, Added<Sleeping>, Without<Counted>)>,
+    mut roll: Single<&mut Roll>,
+    query: Query<
+        (Entity, &Transform, &FaceLayout, &DieFaces, &Owner),
+        (With<Die>, Added<Sleeping>, Without<Counted>),
+    >,
 ) {
     if !count_die.0 {
         return;
     }
-    for (entity, transform) in query.iter() {
-        let sides = vec![
-            (transform.left(), 2),
-            (transform.right(), 5),
-            (transform.up(), 6),
-            (transform.down(), 1),
-            (transform.forward(), 3),
-            (transform.back(), 4),
-        ];
-        if let Some((_, face)) = sides.iter().max_by(|lhs, rhs| {
-            lhs.0
-                .dot(Vec3::Y)
-                .partial_cmp(&rhs.0.dot(Vec3::Y))
-                .expect("comparable")
-        }) {
-            roll.0.faces.push(*face);
-            //roll.0.faces.sort();
-            roll.1.0 = format!(
-                "Roll: {}",
-                roll.0
-                    .faces
-                    .iter()
-                    .map(|face| face.to_string())
-                    .collect::<Vec<_>>()
-                    .join(" + ")
-            );
-        }
+    for (entity, transform, layout, faces, owner) in query.iter() {
+        let face = layout.top_face(transform.rotation);
+        roll.results
+            .push((entity, owner.0, faces.label(face).clone()));
         commands.entity(entity).insert(Counted);
     }
 }
 
-fn move_cup_with_mouse(
-    time: Res<Time>,
+// woken dice are read again once they settle, despawned ones no longer count
+fn uncount_disturbed_dice(
+    mut commands: Commands,
+    mut woken: RemovedComponents<Sleeping>,
+    mut roll: Single<&mut Roll>,
+    counted: Query<(), With<Counted>>,
+) {
+    for entity in woken.read() {
+        if counted.contains(entity) {
+            commands.entity(entity).remove::<Counted>();
+        }
+        if roll.results.iter().any(|(die, _, _)| *die == entity) {
+            roll.results.retain(|(die, _, _)| *die != entity);
+        }
+    }
+}
+
+// a roll is complete once every die of the active player has been read, then the turn passes
+fn resolve_roll(
+    mut count_die: ResMut<CountDie>,
+    mut players: ResMut<Players>,
+    roll: Single<&Roll>,
+    dice: Query<(&Owner, Has<Counted>), With<Die>>,
+    read: Query<(Entity, &Owner), Added<Counted>>,
+    // the player and their dice read since their last completed roll, only these are scored,
+    // so that dice left on the table from earlier turns are not scored again
+    mut reading: Local<(usize, Vec<Entity>)>,
+) {
+    let active = players.active;
+    let (player, read_dice) = &mut *reading;
+    if *player != active {
+        *player = active;
+        read_dice.clear();
+    }
+    for (entity, owner) in read.iter() {
+        if owner.0 == active && !read_dice.contains(&entity) {
+            read_dice.push(entity);
+        }
+    }
+    if !count_die.0 || read_dice.is_empty() {
+        return;
+    }
+    if !dice
+        .iter()
+        .filter(|(owner, _)| owner.0 == active)
+        .all(|(_, counted)| counted)
+    {
+        return;
+    }
+    // faces without a value, like symbols, do not score
+    let total = roll
+        .results
+        .iter()
+        .filter(|(entity, owner, _)| *owner == active && read_dice.contains(entity))
+        .filter_map(|(_, _, label)| label.value)
+        .sum::<i32>();
+    players.list[active].score += total;
+    players.next();
+    count_die.0 = false;
+    read_dice.clear();
+}
+
+fn show_roll(
+    players: Res<Players>,
+    roll: Single<(Ref<Roll>, &mut Text, &mut TextFont)>,
+    concealed: Query<(), With<Concealed>>,
+    added: Query<(), Added<Concealed>>,
+    mut revealed: RemovedComponents<Concealed>,
+) {
+    let (roll, mut text, mut font) = roll.into_inner();
+    let lifted = revealed.read().count() > 0;
+    if roll.is_changed() || players.is_changed() || lifted || !added.is_empty() {
+        text.0 = roll.describe(&players, |entity| concealed.contains(entity));
+        // one line per player has to fit on the screen
+        let font_size = if players.list.len() == 1 { 60.0 } else { 30.0 };
+        if font.font_size != font_size {
+            font.font_size = font_size;
+        }
+    }
+}
+
+fn next_turn(mut players: ResMut<Players>) {
+    players.next();
+}
+
+// hands the cup over when the turn passes, by key, from the panel or after a completed roll
+fn follow_turn(
+    mut commands: Commands,
+    players: Res<Players>,
+    mut cups: Query<(Entity, &Owner, &mut LinearVelocity, Has<ActiveCup>), With<Cup>>,
+) {
+    for (entity, owner, mut linear_velocity, active) in cups.iter_mut() {
+        if active && owner.0 != players.active {
+            commands.entity(entity).remove::<ActiveCup>();
+            linear_velocity.0 = Vector::ZERO;
+        } else if !active && owner.0 == players.active {
+            commands.entity(entity).insert(ActiveCup);
+        }
+    }
+}
+
+// dice and cups belong to seats, so the table is cleared whenever players join, leave or move
+fn seat_players(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    players: Res<Players>,
+    mut roll: Single<&mut Roll>,
+    cups: Query<(Entity, &Owner, &Name), With<Cup>>,
+    dice: Query<Entity, With<Die>>,
+) {
+    let cup_name = |player: &Player| format!("Cup of {}", player.name);
+    let seated = cups.iter().len() == players.list.len()
+        && cups.iter().all(|(_, owner, name)| {
+            players
+                .list
+                .get(owner.0)
+                .is_some_and(|player| name.as_str() == cup_name(player))
+        });
+    if seated {
+        return;
+    }
+    // the dice of the first seating were spawned before any cup
+    if !cups.is_empty() {
+        for entity in cups.iter().map(|(entity, _, _)| entity).chain(dice.iter()) {
+            commands.entity(entity).despawn();
+        }
+        roll.results.clear();
+    }
+    let cup = asset_server.load(GltfAssetLabel::Scene(0).from_asset("Cup mit col.glb"));
+    for (i, player) in players.list.iter().enumerate() {
+        let mut entity = commands.spawn((
+            Cup,
+            Owner(i),
+            SceneRoot(cup.clone()),
+            RigidBody::Kinematic,
+            Name::new(cup_name(player)),
+            Transform::from_translation(players.cup_position(i)),
+        ));
+        if i == players.active {
+            entity.insert(ActiveCup);
+        }
+    }
+}
+
+// remote players write their input on the host, so the mouse is left alone during their turn
+fn read_cup_input(
+    mut cup_input: ResMut<CupInput>,
+    players: Res<Players>,
     window: Single<&Window>,
-    input: Res<ButtonInput<MouseButton>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    keys: Res<ButtonInput<KeyCode>>,
     camera: Single<(&Camera, &GlobalTransform)>,
     ground: Single<&GlobalTransform, With<Ground>>,
-    mut linear_velocity: Single<(&mut LinearVelocity, &Transform), With<Cup>>,
 ) {
-    let movement_speed = 400.0 * time.delta_secs();
-    let (camera, camera_transform) = *camera;
-    let Some(cursor) = window.cursor_position() else {
+    if players.active().remote {
         return;
-    };
-    let Ok(ray) = camera.viewport_to_world(camera_transform, cursor) else {
-        return;
-    };
-    let Some(distance) =
-        ray.intersect_plane(ground.translation(), InfinitePlane3d::new(ground.up()))
-    else {
+    }
+    let (camera, camera_transform) = *camera;
+    cup_input.target = window
+        .cursor_position()
+        .and_then(|cursor| camera.viewport_to_world(camera_transform, cursor).ok())
+        .and_then(|ray| {
+            ray.intersect_plane(ground.translation(), InfinitePlane3d::new(ground.up()))
+                .map(|distance| ray.get_point(distance))
+        });
+    cup_input.lift = mouse.pressed(MouseButton::Left) as u8 as f32
+        - mouse.pressed(MouseButton::Right) as u8 as f32;
+    cup_input.tilt = keys.pressed(KeyCode::KeyR);
+}
+
+#[allow(clippy::type_complexity)]
+fn move_active_cup(
+    tuning: Res<PhysicsTuning>,
+    cup_input: Res<CupInput>,
+    mut linear_velocity: Single<(&mut LinearVelocity, &Transform), (With<Cup>, With<ActiveCup>)>,
+) {
+    let Some(point) = cup_input.target else {
         return;
     };
-    let point = ray.get_point(distance);
     let translation = linear_velocity.1.translation;
     let target_point = Vec3::new(point.x, translation.y, point.z);
 
     let max = Vec3::ONE * 1000.0;
     let move_towards = target_point - translation;
     let distance = translation.distance(target_point);
-    linear_velocity.0.0 = (move_towards * distance * 8.0).clamp(-max, max);
-
-    if input.pressed(MouseButton::Right) {
-        linear_velocity.0.0.y -= movement_speed;
-    }
-    if input.pressed(MouseButton::Left) {
-        linear_velocity.0.0.y += movement_speed;
-    }
+    linear_velocity.0.0 = (move_towards * distance * tuning.cup_chase_gain).clamp(-max, max);
+    linear_velocity.0.0.y += cup_input.lift * CUP_LIFT_SPEED;
 }
 
 fn roll_cup_towards_center(
+    tuning: Res<PhysicsTuning>,
     mut count_die: ResMut<CountDie>,
-    input: Res<ButtonInput<KeyCode>>,
+    cup_input: Res<CupInput>,
     ground: Single<&GlobalTransform, With<Ground>>,
-    mut angular_velocity: Single<(&mut AngularVelocity, &Transform), With<Cup>>,
+    mut cups: Query<(&mut AngularVelocity, &Transform, Has<ActiveCup>), With<Cup>>,
 ) {
     let center = ground.translation();
-    let direction = (center - angular_velocity.1.translation).normalize();
-    let target_up = if input.pressed(KeyCode::KeyR) {
-        count_die.0 = true;
-        direction
-    } else {
-        Vec3::Y
-    };
-
-    **angular_velocity.0 =
-        Quat::from_rotation_arc(*angular_velocity.1.up(), target_up).to_scaled_axis() * 4.0;
-}
-
-fn detect_sleep(
-    mut commands: Commands,
-    deactivation_time: Res<DeactivationTime>,
-    mut query: Query<(Entity, &Transform, &mut AutoSleep, &GravityScale), Without<Sleeping>>,
-    time: Res<Time>,
-) {
-    for (entity, transform, mut auto_sleep, gravity_scale) in query.iter_mut() {
-        let translation = transform.translation;
-        let rotation = transform.rotation.to_scaled_axis();
-        let changed = (auto_sleep.translation - translation).length()
-            + (auto_sleep.rotation - rotation).length();
-        if changed > 0.1 {
-            if gravity_scale.0 != 20.0 {
-                commands.entity(entity).insert(GravityScale(20.0));
-                commands.entity(entity).insert(LinearDamping::default());
-                commands.entity(entity).insert(AngularDamping::default());
-            }
-            auto_sleep.translation = translation;
-            auto_sleep.rotation = rotation;
-            auto_sleep.time = 0.0;
+    // the other cups stand upright while they wait for their turn
+    for (mut angular_velocity, transform, active) in cups.iter_mut() {
+        let target_up = if active && cup_input.tilt {
+            count_die.0 = true;
+            (center - transform.translation).normalize()
         } else {
-            auto_sleep.time += time.delta_secs();
-        }
-        if auto_sleep.time > deactivation_time.0 {
-            //info!("auto sleeping for {entity}");
-            commands.entity(entity).insert(Sleeping);
-            if gravity_scale.0 != 1.0 {
-                commands.entity(entity).insert(GravityScale(1.0));
-                commands.entity(entity).insert(LinearDamping(100000.0));
-                commands.entity(entity).insert(AngularDamping(100000.0));
-            }
-        }
+            Vec3::Y
+        };
+        angular_velocity.0 = Quat::from_rotation_arc(*transform.up(), target_up).to_scaled_axis()
+            * tuning.cup_tilt_gain;
     }
 }
 
@@ -421,26 +924,72 @@ fn position_cursor(
     cursor.direction = ray.direction;
 }
 
-fn highlight_selected_die(
-    dice: Query<&MeshMaterial3d<StandardMaterial>, With<Die>>,
-    cursor: Query<(&RayCaster, &RayHits), With<Cursor>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+// the mesh handle is only written when a die crosses a lod distance
+fn select_lod(
+    camera: Single<&GlobalTransform, With<Camera3d>>,
+    mut dice: Query<(&GlobalTransform, &Lods, &mut Mesh3d), With<Die>>,
 ) {
-    for mesh_material in dice.iter() {
-        let mesh_material = materials.get_mut(mesh_material).expect("mesh_material");
-        mesh_material.base_color = Color::default();
-    }
-    for (_ray, hits) in cursor.iter() {
-        for hit in hits.iter_sorted() {
-            if let Ok(mesh_material) = dice.get(hit.entity) {
-                let mesh_material = materials.get_mut(mesh_material).expect("mesh_material");
-                mesh_material.base_color = Color::srgb(0.0, 0.8, 0.8);
-            }
-            break;
+    for (transform, lods, mut mesh) in dice.iter_mut() {
+        let distance = camera.translation().distance(transform.translation());
+        let (_, lod) = lods
+            .0
+            .iter()
+            .rev()
+            .find(|(from, _)| distance >= *from)
+            .expect("the first lod starts at 0");
+        if mesh.0 != *lod {
+            mesh.0 = lod.clone();
         }
     }
 }
 
+// the active player holds P to peek under their own cup
+fn conceal_dice(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    players: Res<Players>,
+    mut dice: Query<(&Owner, &mut Visibility, Has<Concealed>), With<Die>>,
+) {
+    let peeking = keyboard.pressed(KeyCode::KeyP);
+    for (owner, mut visibility, concealed) in dice.iter_mut() {
+        let hidden = concealed && !(peeking && owner.0 == players.active);
+        let wanted = if hidden {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+        if *visibility != wanted {
+            *visibility = wanted;
+        }
+    }
+}
+
+// only the previously and the currently hovered die are touched
+fn highlight_selected_die(
+    mut dice: Query<&mut Highlighted, With<Die>>,
+    cursor: Query<&RayHits, With<Cursor>>,
+    mut hovered: Local<Option<Entity>>,
+) {
+    let hit = cursor
+        .iter()
+        .find_map(|hits| hits.iter_sorted().next())
+        .map(|hit| hit.entity)
+        .filter(|entity| dice.contains(*entity));
+    if hit == *hovered {
+        return;
+    }
+    if let Some(previous) = hovered.take()
+        && let Ok(mut highlighted) = dice.get_mut(previous)
+    {
+        highlighted.0 = false;
+    }
+    if let Some(entity) = hit
+        && let Ok(mut highlighted) = dice.get_mut(entity)
+    {
+        highlighted.0 = true;
+    }
+    *hovered = hit;
+}
+
 fn toggle_debug_render(
     mut gizmo_config: ResMut<GizmoConfigStore>,
     mut flag: ResMut<DebugRenderEnabled>,
@@ -464,52 +1013,204 @@ fn toggle_debug_render(
 }
 
 // TODO compare with: https://docs.rs/avian3d/latest/avian3d/collision/collider/struct.ColliderConstructorHierarchy.html
-fn handle_asset_events(
+// cups are seated again when the players change, so this runs for every cup and not once per asset
+#[allow(clippy::too_many_arguments)]
+fn prepare_cup(
+    trigger: Trigger<SceneInstanceReady>,
     mut commands: Commands,
-    query: Query<(Entity, &SceneRoot), With<RigidBody>>,
+    cups: Query<&Owner, With<Cup>>,
     children: Query<&Children>,
     mesh_query: Query<(&Mesh3d, &Name)>,
-    mut events: EventReader<AssetEvent<Scene>>,
     meshes: Res<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    cache: Res<Cache>,
+    players: Res<Players>,
 ) {
-    for event in events.read() {
-        if let AssetEvent::LoadedWithDependencies { id } = event {
-            for (entity, scene_root) in query.iter() {
-                if scene_root.0.id() == *id {
-                    for entity in children.iter_descendants(entity) {
-                        let Ok((mesh, name)) = mesh_query.get(entity) else {
-                            continue;
-                        };
-                        //info!("{:?}", name);
-                        if name.starts_with("Cube") {
-                            commands.entity(entity).insert(Visibility::Hidden);
-                        } else {
-                            commands.entity(entity).insert(MeshMaterial3d(materials.add(
-                                StandardMaterial {
-                                    base_color: Color::srgba(1.0, 1.0, 1.0, 0.5),
-                                    alpha_mode: AlphaMode::Add,
-                                    ..default()
-                                },
-                            )));
-                        }
-                        if !name.starts_with("Cylinder.001") {
-                            continue;
-                        }
-                        commands.entity(entity).insert(Visibility::Hidden);
-                        let mesh = meshes.get(mesh.0.id()).expect("mesh");
-                        let collider = Collider::convex_decomposition_from_mesh_with_config(
-                            mesh,
-                            &VhacdParameters {
-                                fill_mode: FillMode::SurfaceOnly,
-                                ..default()
-                            },
-                        )
-                        .expect("collider");
-                        commands.entity(entity).with_child(collider);
-                    }
-                }
+    let Ok(owner) = cups.get(trigger.target()) else {
+        return;
+    };
+    // every cup is tinted with the color of its owner's dice
+    let tint = players
+        .list
+        .get(owner.0)
+        .map_or(Color::WHITE, |player| player.style.body);
+    for entity in children.iter_descendants(trigger.target()) {
+        let Ok((mesh, name)) = mesh_query.get(entity) else {
+            continue;
+        };
+        //info!("{:?}", name);
+        if name.starts_with("Cube") {
+            commands.entity(entity).insert(Visibility::Hidden);
+        } else {
+            commands
+                .entity(entity)
+                .insert(MeshMaterial3d(materials.add(StandardMaterial {
+                    base_color: tint.with_alpha(0.5),
+                    alpha_mode: AlphaMode::Add,
+                    ..default()
+                })));
+        }
+        if !name.starts_with("Cylinder.001") {
+            continue;
+        }
+        commands.entity(entity).insert(Visibility::Hidden);
+        let mesh = meshes.get(mesh.0.id()).expect("mesh");
+        let key = CacheKey::new("cup collider").mesh(mesh);
+        let collider = cache.collider(key, || {
+            Collider::convex_decomposition_from_mesh_with_config(
+                mesh,
+                &VhacdParameters {
+                    fill_mode: FillMode::SurfaceOnly,
+                    ..default()
+                },
+            )
+            .ok_or(GeometryError::ColliderGeneration)
+        });
+        let collider = match collider {
+            Ok(collider) => collider,
+            Err(error) => {
+                warn!("the cup has no collider: {error}");
+                continue;
             }
+        };
+        commands.entity(entity).with_child((CupCollider, collider));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::die::d6_collider;
+    use bevy::scene::ScenePlugin;
+    use bevy::time::TimeUpdateStrategy;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use std::f32::consts::TAU;
+    use std::time::Duration;
+
+    const STEPS: u32 = 64 * 6;
+
+    #[derive(Resource, Default)]
+    struct Outcome {
+        steps: u32,
+        dice: Vec<(u8, Vec3, bool)>,
+    }
+
+    // spins the dice for half a second and records them once all steps ran
+    fn observe(
+        mut outcome: ResMut<Outcome>,
+        mut spinning: ResMut<Spinning>,
+        dice: Query<(&Transform, &FaceLayout, Has<Sleeping>), With<Die>>,
+    ) {
+        outcome.steps += 1;
+        spinning.0 = outcome.steps < 32;
+        if outcome.steps == STEPS {
+            outcome.dice = dice
+                .iter()
+                .map(|(transform, layout, sleeping)| {
+                    let face = layout.top_face(transform.rotation);
+                    (face, transform.translation, sleeping)
+                })
+                .collect();
         }
     }
+
+    fn roll_at(frame_rate: f64) -> Vec<(u8, Vec3, bool)> {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            TransformPlugin,
+            AssetPlugin::default(),
+            ScenePlugin,
+            PhysicsPlugins::default(),
+            SettlePlugin,
+        ))
+        .init_asset::<Mesh>()
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+            1.0 / frame_rate,
+        )))
+        .init_resource::<Spinning>()
+        .init_resource::<Outcome>()
+        .add_systems(FixedUpdate, spin)
+        .add_systems(FixedLast, observe);
+        app.finish();
+        app.cleanup();
+
+        let mesh = DieShape::CLASSIC
+            .mesh(&FaceLayout::default(), 2)
+            .expect("mesh");
+        let collider = d6_collider(&mesh, &DieShape::CLASSIC, ColliderMode::ConvexHull).unwrap();
+        let mut rng = StdRng::seed_from_u64(7);
+        let world = app.world_mut();
+        world.spawn((
+            RigidBody::Static,
+            Collider::cuboid(20.0, 0.2, 20.0),
+            Transform::default(),
+        ));
+        let mut commands = world.commands();
+        for i in 0..6 {
+            let rotation = Quat::from_euler(
+                EulerRot::XYZ,
+                rng.random_range(0.0..TAU),
+                rng.random_range(0.0..TAU),
+                rng.random_range(0.0..TAU),
+            );
+            let spin = Vec3::new(
+                rng.random_range(-1.0..1.0),
+                rng.random_range(-1.0..1.0),
+                rng.random_range(-1.0..1.0),
+            );
+            Die::builder()
+                .transform(Transform::from_xyz(i as f32 - 2.5, 3.0, 0.0).with_rotation(rotation))
+                .spawn(&mut commands, collider.clone())
+                .insert((AutoSleep::default(), Spinnable(spin * 12.5)));
+        }
+        world.flush();
+
+        while app.world().resource::<Outcome>().steps < STEPS {
+            app.update();
+        }
+        app.world_mut()
+            .remove_resource::<Outcome>()
+            .expect("outcome")
+            .dice
+    }
+
+    #[test]
+    fn rolls_do_not_depend_on_the_frame_rate() {
+        let expected = roll_at(60.0);
+        assert_eq!(expected.len(), 6);
+        for frame_rate in [30.0, 144.0] {
+            let dice = roll_at(frame_rate);
+            assert_eq!(dice.len(), expected.len());
+            for ((face, position, sleeping), expected) in dice.into_iter().zip(&expected) {
+                assert_eq!(
+                    (face, sleeping),
+                    (expected.0, expected.2),
+                    "{frame_rate} Hz"
+                );
+                assert!(position.distance(expected.1) < 1e-3, "{frame_rate} Hz");
+            }
+        }
+    }
+
+    #[test]
+    fn dice_from_earlier_turns_are_not_scored_again() {
+        let mut app = App::new();
+        app.insert_resource(CountDie(true))
+            .insert_resource(Players::new(1).unwrap())
+            .add_systems(Update, resolve_roll);
+        let roll = app.world_mut().spawn(Roll { results: vec![] }).id();
+        let read = |app: &mut App, value: i32| {
+            let die = app.world_mut().spawn((Die, Owner(0), Counted)).id();
+            let mut results = app.world_mut().get_mut::<Roll>(roll).unwrap();
+            results.results.push((die, 0, FaceLabel::number(value)));
+            app.world_mut().resource_mut::<CountDie>().0 = true;
+            app.update();
+            app.world().resource::<Players>().list[0].score
+        };
+        assert_eq!(read(&mut app, 3), 3);
+        // the first die stays on the table and counted
+        assert_eq!(read(&mut app, 4), 7);
+    }
 }
@@ -0,0 +1,559 @@
+use crate::cli::{has_flag, value_of};
+use crate::die::Die;
+use crate::faces::DieFaces;
+use crate::game::GameMode;
+use crate::players::{ActiveCup, Owner, Player, Players};
+use crate::protocol::{Connection, Message};
+use crate::style::DieStyle;
+use crate::{Concealed, Cup, CupInput, DieSpawner, Roll};
+use avian3d::prelude::*;
+use bevy::input::common_conditions::input_just_pressed;
+use bevy::prelude::*;
+use std::collections::HashMap;
+use std::net::{TcpListener, TcpStream};
+
+pub enum NetConfig {
+    Host { address: String, headless: bool },
+    Join { address: String, name: String },
+}
+
+// the host simulates the table and sends it to everyone who joined
+pub struct HostPlugin {
+    pub address: String,
+    pub headless: bool,
+}
+
+// a client shows the table of the host and sends the input for its own cup
+pub struct ClientPlugin {
+    pub address: String,
+    pub name: String,
+}
+
+#[derive(Resource)]
+struct NetHost {
+    listener: TcpListener,
+    clients: Vec<Client>,
+    // someone joined since the last snapshot, so everything is sent again
+    joined: bool,
+    headless: bool,
+}
+
+struct Client {
+    connection: Connection,
+    // the seat of the client once it joined
+    player: Option<usize>,
+}
+
+#[derive(Resource)]
+struct NetClient {
+    connection: Connection,
+    player: Option<usize>,
+    // the copies of the host's dice, by the host's entity
+    dice: HashMap<u64, Entity>,
+    // the dice and players of the snapshot that is being received
+    seen: Vec<u64>,
+    players: Option<Players>,
+}
+
+// a remote player asked for a die in their cup
+#[derive(Event)]
+struct SpawnInCup;
+
+impl NetConfig {
+    // dice --host <address> [--headless] | --join <address> [--name <name>]
+    // a headless host only has the free table, see check_game
+    pub fn from_args(args: &[String]) -> Option<Self> {
+        if let Some(address) = value_of(args, "--host") {
+            return Some(NetConfig::Host {
+                address: String::from(address),
+                headless: has_flag(args, "--headless"),
+            });
+        }
+        let address = value_of(args, "--join")?;
+        Some(NetConfig::Join {
+            address: String::from(address),
+            name: String::from(value_of(args, "--name").unwrap_or("Guest")),
+        })
+    }
+
+    // the games are played in the panels of the host, which a headless host does not show, and
+    // the protocol only carries the input of the cups
+    pub fn check_game(&self, mode: GameMode) -> Result<GameMode, String> {
+        match self {
+            NetConfig::Host { headless: true, .. } if mode != GameMode::Free => Err(format!(
+                "{} needs a window on the host, a headless host only rolls freely",
+                mode.name()
+            )),
+            _ => Ok(mode),
+        }
+    }
+}
+
+impl Plugin for HostPlugin {
+    fn build(&self, app: &mut App) {
+        let listener = TcpListener::bind(&self.address).expect("--host");
+        listener.set_nonblocking(true).expect("--host");
+        info!("hosting a table on {}", self.address);
+        app.insert_resource(NetHost {
+            listener,
+            clients: vec![],
+            joined: false,
+            headless: self.headless,
+        })
+        .add_event::<SpawnInCup>()
+        .add_systems(
+            Update,
+            (accept_clients, receive_from_clients, spawn_in_cup).chain(),
+        )
+        // after the physics and the roll, so that clients see the same table as the host
+        .add_systems(PostUpdate, send_table);
+    }
+}
+
+impl Plugin for ClientPlugin {
+    fn build(&self, app: &mut App) {
+        let stream = TcpStream::connect(&self.address).expect("--join");
+        let mut connection = Connection::new(stream).expect("--join");
+        connection.send(&Message::Join(self.name.clone()));
+        info!("joining the table on {}", self.address);
+        app.insert_resource(NetClient {
+            connection,
+            player: None,
+            dice: HashMap::new(),
+            seen: vec![],
+            players: None,
+        })
+        .add_systems(
+            Update,
+            (
+                receive_table,
+                request_die.run_if(input_just_pressed(KeyCode::Enter)),
+                send_input,
+            )
+                .chain(),
+        );
+    }
+}
+
+fn accept_clients(mut host: ResMut<NetHost>) {
+    while let Ok((stream, address)) = host.listener.accept() {
+        match Connection::new(stream) {
+            Ok(connection) => {
+                info!("{address} connected");
+                host.clients.push(Client {
+                    connection,
+                    player: None,
+                });
+            }
+            Err(error) => warn!("could not accept {address}: {error}"),
+        }
+    }
+}
+
+// clients only steer the cup and spawn dice during their own turn
+fn receive_from_clients(
+    mut host: ResMut<NetHost>,
+    mut players: ResMut<Players>,
+    mut cup_input: ResMut<CupInput>,
+    mut spawn: EventWriter<SpawnInCup>,
+) {
+    let host = &mut *host;
+    let mut left = vec![];
+    for (i, client) in host.clients.iter_mut().enumerate() {
+        let messages = match client.connection.receive() {
+            Ok(messages) => messages,
+            Err(error) => {
+                info!("a client left: {error}");
+                left.push(i);
+                continue;
+            }
+        };
+        for message in messages {
+            match message {
+                Message::Join(name) if client.player.is_none() => {
+                    let Some(player) = seat(&mut players, &name) else {
+                        warn!("there is no seat left for {name}");
+                        left.push(i);
+                        break;
+                    };
+                    client.player = Some(player);
+                    client.connection.send(&Message::Welcome(player));
+                    host.joined = true;
+                }
+                Message::Input { target, lift, tilt } if client.player == Some(players.active) => {
+                    cup_input.target = Some(target);
+                    cup_input.lift = lift.clamp(-1.0, 1.0);
+                    cup_input.tilt = tilt;
+                }
+                Message::Spawn if client.player == Some(players.active) => {
+                    spawn.write(SpawnInCup);
+                }
+                _ => {}
+            }
+        }
+    }
+    for i in left.into_iter().rev() {
+        if let Some(player) = host.clients.remove(i).player {
+            host.remove_player(&mut players, player);
+        }
+    }
+    // nobody sits at a headless host, so only the players of clients keep their seats
+    if host.headless && host.clients.iter().any(|client| client.player.is_some()) {
+        for player in (0..players.list.len()).rev() {
+            if host
+                .clients
+                .iter()
+                .all(|client| client.player != Some(player))
+            {
+                host.remove_player(&mut players, player);
+            }
+        }
+    }
+}
+
+impl NetHost {
+    // seats are indices, so the players after a removed one move up
+    fn remove_player(&mut self, players: &mut Players, player: usize) {
+        if let Err(error) = players.remove(player) {
+            warn!("{error}");
+            return;
+        }
+        for client in self.clients.iter_mut() {
+            if let Some(seat) = client.player.as_mut()
+                && *seat > player
+            {
+                *seat -= 1;
+                client.connection.send(&Message::Welcome(*seat));
+            }
+        }
+    }
+}
+
+// a name that is already taken gets a number
+fn seat(players: &mut Players, name: &str) -> Option<usize> {
+    (1..=Players::MAX).find_map(|n| {
+        let name = if n == 1 {
+            String::from(name)
+        } else {
+            format!("{name} {n}")
+        };
+        players.add(&name).ok()?;
+        let player = players.list.len() - 1;
+        players.list[player].remote = true;
+        Some(player)
+    })
+}
+
+fn spawn_in_cup(
+    mut events: EventReader<SpawnInCup>,
+    mut spawner: DieSpawner,
+    cup: Single<&Transform, (With<Cup>, With<ActiveCup>)>,
+) {
+    for _ in events.read() {
+        spawner.spawn(Transform::from_translation(cup.translation + Vec3::Y * 2.0));
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn send_table(
+    mut host: ResMut<NetHost>,
+    players: Res<Players>,
+    roll: Single<Ref<Text>, With<Roll>>,
+    cups: Query<(&Owner, &Transform), With<Cup>>,
+    dice: Query<
+        (
+            Entity,
+            &Owner,
+            &Transform,
+            &DieStyle,
+            &DieFaces,
+            Has<Concealed>,
+        ),
+        With<Die>,
+    >,
+) {
+    let mut messages = vec![];
+    if players.is_changed() || host.joined {
+        messages.push(Message::Players(players.active));
+        messages.extend(players.list.iter().map(|player| Message::Player {
+            score: player.score,
+            style: player.style,
+            name: player.name.clone(),
+        }));
+    }
+    if roll.is_changed() || host.joined {
+        messages.push(Message::Roll(roll.0.clone()));
+    }
+    host.joined = false;
+    messages.extend(cups.iter().map(|(owner, transform)| Message::Cup {
+        owner: owner.0,
+        transform: *transform,
+    }));
+    // dice under a cup are only sent to their owner, so that nobody can peek at them
+    let dice = dice
+        .iter()
+        .map(|(entity, owner, transform, style, faces, concealed)| {
+            let message = Message::Die {
+                id: entity.to_bits(),
+                owner: owner.0,
+                transform: *transform,
+                style: *style,
+                faces: faces.name.clone(),
+            };
+            (concealed.then_some(owner.0), message)
+        })
+        .collect::<Vec<_>>();
+    for client in host.clients.iter_mut() {
+        if client.player.is_none() {
+            continue;
+        }
+        for message in &messages {
+            client.connection.send(message);
+        }
+        for (_, message) in dice
+            .iter()
+            .filter(|(concealed, _)| concealed.is_none_or(|owner| client.player == Some(owner)))
+        {
+            client.connection.send(message);
+        }
+        client.connection.send(&Message::Frame);
+        // a broken connection fails to receive as well, which is where clients are dropped
+        let _ = client.connection.flush();
+    }
+}
+
+// the dice are copied as kinematic bodies, so that only the host simulates them
+#[allow(clippy::type_complexity)]
+fn receive_table(
+    mut client: ResMut<NetClient>,
+    mut spawner: DieSpawner,
+    mut cups: Query<(&Owner, &mut Transform), With<Cup>>,
+    mut dice: Query<(&mut Transform, &mut DieStyle), (With<Die>, Without<Cup>)>,
+    mut roll: Single<&mut Text, With<Roll>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let client = &mut *client;
+    let messages = match client.connection.receive() {
+        Ok(messages) => messages,
+        Err(error) => {
+            error!("lost the connection to the host: {error}");
+            exit.write(AppExit::error());
+            return;
+        }
+    };
+    for message in messages {
+        match message {
+            Message::Welcome(player) => client.player = Some(player),
+            Message::Players(active) => {
+                client.players = Some(Players {
+                    list: vec![],
+                    active,
+                })
+            }
+            Message::Player { score, style, name } => {
+                if let Some(players) = client.players.as_mut() {
+                    players.list.push(Player {
+                        name,
+                        style,
+                        score,
+                        remote: false,
+                    });
+                }
+            }
+            Message::Roll(text) => roll.0 = text,
+            Message::Cup { owner, transform } => {
+                for (cup_owner, mut cup_transform) in cups.iter_mut() {
+                    if cup_owner.0 == owner {
+                        *cup_transform = transform;
+                    }
+                }
+            }
+            Message::Die {
+                id,
+                owner,
+                transform,
+                style,
+                faces,
+            } => {
+                client.seen.push(id);
+                if let Some(entity) = client.dice.get(&id)
+                    && let Ok((mut die_transform, mut die_style)) = dice.get_mut(*entity)
+                {
+                    *die_transform = transform;
+                    if *die_style != style {
+                        *die_style = style;
+                    }
+                    continue;
+                }
+                // custom faces are named by their labels, so they can be rebuilt from the name
+                let faces = DieFaces::parse(&faces).unwrap_or_else(|_| DieFaces::numbered());
+                let Some(entity) = spawner.spawn_with(transform, faces) else {
+                    continue;
+                };
+                spawner.commands.entity(entity).insert((
+                    RigidBody::Kinematic,
+                    LinearVelocity::ZERO,
+                    AngularVelocity::ZERO,
+                    Owner(owner),
+                    style,
+                ));
+                client.dice.insert(id, entity);
+            }
+            Message::Frame => {
+                let seen = std::mem::take(&mut client.seen);
+                client.dice.retain(|id, entity| {
+                    let keep = seen.contains(id);
+                    if !keep && dice.contains(*entity) {
+                        spawner.commands.entity(*entity).despawn();
+                    }
+                    keep
+                });
+                if let Some(players) = client.players.take() {
+                    spawner.commands.insert_resource(players);
+                }
+            }
+            Message::Join(_) | Message::Input { .. } | Message::Spawn => {
+                warn!("the host sent a message meant for the host");
+            }
+        }
+    }
+}
+
+fn request_die(mut client: ResMut<NetClient>) {
+    client.connection.send(&Message::Spawn);
+}
+
+fn send_input(mut client: ResMut<NetClient>, players: Res<Players>, cup_input: Res<CupInput>) {
+    if client.player == Some(players.active)
+        && let Some(target) = cup_input.target
+    {
+        client.connection.send(&Message::Input {
+            target,
+            lift: cup_input.lift,
+            tilt: cup_input.tilt,
+        });
+    }
+    // a broken connection fails to receive as well, which is where the client stops
+    let _ = client.connection.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atlas::{Marking, SelectedMarking};
+    use crate::cache::Cache;
+    use crate::die::{ColliderMode, DieShape, Engraved, SelectedShape};
+    use crate::faces::SelectedFaces;
+    use crate::layout::FaceLayout;
+    use crate::tuning::PhysicsTuning;
+    use crate::{D6, move_active_cup};
+    use bevy::scene::ScenePlugin;
+    use bevy::time::TimeUpdateStrategy;
+    use std::time::Duration;
+
+    #[test]
+    fn headless_hosts_only_roll_freely() {
+        let host = |headless| NetConfig::Host {
+            address: String::from("127.0.0.1:0"),
+            headless,
+        };
+        assert_eq!(host(true).check_game(GameMode::Free), Ok(GameMode::Free));
+        assert!(host(true).check_game(GameMode::Farkle).is_err());
+        assert_eq!(
+            host(false).check_game(GameMode::Farkle),
+            Ok(GameMode::Farkle)
+        );
+    }
+
+    #[test]
+    fn guests_join_and_steer_their_cup() {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            TransformPlugin,
+            AssetPlugin::default(),
+            ScenePlugin,
+            PhysicsPlugins::default(),
+            HostPlugin {
+                address: String::from("127.0.0.1:0"),
+                headless: true,
+            },
+        ))
+        .init_asset::<Mesh>()
+        .init_asset::<Image>()
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+            1.0 / 60.0,
+        )))
+        .insert_resource(Players::new(1).unwrap())
+        .insert_resource(CupInput::default())
+        .insert_resource(PhysicsTuning::default())
+        .insert_resource(D6 {
+            layout: FaceLayout::default(),
+            collider_mode: ColliderMode::default(),
+            shapes: HashMap::new(),
+            engraved: HashMap::new(),
+            atlases: HashMap::new(),
+        })
+        .insert_resource(Cache::disabled())
+        .insert_resource(SelectedFaces(DieFaces::numbered()))
+        .insert_resource(SelectedMarking(Marking::default()))
+        .insert_resource(SelectedShape(DieShape::default()))
+        .insert_resource(Engraved(false))
+        .add_systems(FixedUpdate, move_active_cup);
+        app.finish();
+        app.cleanup();
+        let world = app.world_mut();
+        world.spawn((Roll { results: vec![] }, Text::new("Roll:")));
+        let cup = world
+            .spawn((
+                Cup,
+                Owner(0),
+                ActiveCup,
+                RigidBody::Kinematic,
+                Transform::from_xyz(2.0, 1.2, 0.0),
+            ))
+            .id();
+
+        let address = app
+            .world()
+            .resource::<NetHost>()
+            .listener
+            .local_addr()
+            .unwrap();
+        let mut client = Connection::new(TcpStream::connect(address).unwrap()).unwrap();
+        client.send(&Message::Join(String::from("Ada")));
+        // nobody sits at a headless host, so the guest moves up to the first seat
+        let mut seat = None;
+        for _ in 0..1000 {
+            client.flush().unwrap();
+            app.update();
+            for message in client.receive().unwrap() {
+                if let Message::Welcome(player) = message {
+                    seat = Some(player);
+                }
+            }
+            if seat == Some(0) {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(seat, Some(0));
+        let players = app.world().resource::<Players>();
+        assert_eq!(players.list.len(), 1);
+        assert_eq!(players.active().name, "Ada");
+
+        client.send(&Message::Input {
+            target: Vec3::new(-2.0, 0.0, 0.0),
+            lift: 0.0,
+            tilt: false,
+        });
+        for _ in 0..60 {
+            client.flush().unwrap();
+            app.update();
+            client.receive().unwrap();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        let x = app.world().get::<Transform>(cup).unwrap().translation.x;
+        assert!(x < 1.0, "the cup is still at {x}");
+    }
+}
@@ -0,0 +1,497 @@
+use crate::die::Die;
+use crate::faces::DieFaces;
+use crate::game::{GameMode, Seats, clear_for_deal, shake_dice};
+use crate::players::{Owner, Players};
+use crate::{Concealed, CountDie, Counted, Cup, DieSpawner, Roll};
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{EguiContextPass, EguiContexts, egui};
+use std::collections::HashSet;
+
+// liar's dice, everyone bids on the dice under all cups without seeing more than their own
+pub struct PerudoPlugin;
+
+const STARTING_DICE: usize = 5;
+
+// where the dice are placed in a cup, the last one on top of the others
+const SPOTS: [Vec3; STARTING_DICE] = [
+    Vec3::new(-0.4, 1.6, -0.4),
+    Vec3::new(0.4, 1.6, -0.4),
+    Vec3::new(-0.4, 1.6, 0.4),
+    Vec3::new(0.4, 1.6, 0.4),
+    Vec3::new(0.0, 2.4, 0.0),
+];
+
+// at least count dice on the table show face, counting the wild ones
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Bid {
+    pub count: usize,
+    pub face: u8,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Phase {
+    // every cup holds the dice its player has left
+    Shaking,
+    Settling,
+    Bidding,
+    Revealed { loser: usize },
+    Over { winner: usize },
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Action {
+    Shake,
+    Bid(Bid),
+    // the active player doubts the last bid and every cup is lifted
+    Challenge,
+    NextRound,
+    NewGame,
+}
+
+#[derive(Resource)]
+pub struct Perudo {
+    phase: Phase,
+    // the dice every player has left, by seat, a player without dice is out
+    dice: Vec<usize>,
+    // the bid to raise or challenge, and who made it
+    bid: Option<(usize, Bid)>,
+    // the players the computer bids for, by name so that they survive reseating
+    computer: HashSet<String>,
+    // clicked in the panel or chosen by the computer, carried out by play_perudo
+    action: Option<Action>,
+    deal: bool,
+    // the game starts over when somebody joins, leaves or moves, as the dice are kept by seat
+    seats: Seats,
+    // the computer waits before it moves, so that its bids can be followed
+    thinking: Timer,
+    outcome: String,
+}
+
+impl Plugin for PerudoPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                start_perudo.run_if(resource_changed::<GameMode>),
+                (play_perudo, deal_dice).run_if(resource_exists::<Perudo>),
+            )
+                .chain(),
+        )
+        .add_systems(
+            EguiContextPass,
+            perudo_panel.run_if(resource_exists::<Perudo>),
+        );
+    }
+}
+
+impl Bid {
+    // more dice, or as many of a higher face, where a bid on ones needs only half as many
+    pub fn raises(&self, previous: Option<&Bid>) -> bool {
+        if self.count == 0 || !(1..=6).contains(&self.face) {
+            return false;
+        }
+        let Some(previous) = previous else {
+            // a round cannot be opened on the wild ones
+            return self.face != 1;
+        };
+        match (self.face == 1, previous.face == 1) {
+            (false, false) => {
+                self.count > previous.count
+                    || (self.count == previous.count && self.face > previous.face)
+            }
+            (true, true) => self.count > previous.count,
+            (true, false) => self.count >= previous.count.div_ceil(2),
+            (false, true) => self.count > previous.count * 2,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        format!("{} × {}", self.count, self.face)
+    }
+}
+
+// ones are wild and count for every face
+pub fn matching(values: &[u8], face: u8) -> usize {
+    values
+        .iter()
+        .filter(|value| **value == face || **value == 1)
+        .count()
+}
+
+// the computer expects its own matching dice and a third of the dice it cannot see, a sixth for ones,
+// and doubts a bid it cannot raise without going well beyond that
+fn computer_move(own: &[u8], unknown: usize, previous: Option<Bid>) -> Action {
+    let expected = |face: u8| {
+        let share = if face == 1 { 6.0 } else { 3.0 };
+        matching(own, face) as f32 + unknown as f32 / share
+    };
+    let total = own.len() + unknown;
+    let best = (1..=6)
+        .filter_map(|face| {
+            let bid = (1..=total)
+                .map(|count| Bid { count, face })
+                .find(|bid| bid.raises(previous.as_ref()))?;
+            Some((expected(face) - bid.count as f32, bid))
+        })
+        .max_by(|a, b| a.0.total_cmp(&b.0));
+    match best {
+        Some((margin, bid)) if previous.is_none() || margin > -1.0 => Action::Bid(bid),
+        _ => Action::Challenge,
+    }
+}
+
+// the turn skips players who are out
+fn pass_turn(players: &mut Players, dice: &[usize]) {
+    for _ in 0..players.list.len() {
+        players.next();
+        if dice.get(players.active).is_some_and(|dice| *dice > 0) {
+            return;
+        }
+    }
+}
+
+impl Perudo {
+    fn new(players: &Players) -> Self {
+        Perudo {
+            phase: Phase::Shaking,
+            dice: vec![STARTING_DICE; players.list.len()],
+            bid: None,
+            computer: HashSet::new(),
+            action: None,
+            deal: true,
+            seats: Seats::new(players),
+            thinking: Timer::from_seconds(1.5, TimerMode::Repeating),
+            outcome: String::new(),
+        }
+    }
+
+    fn restart(&mut self, players: &Players) {
+        let computer = std::mem::take(&mut self.computer);
+        *self = Perudo {
+            computer,
+            ..Perudo::new(players)
+        };
+    }
+}
+
+fn start_perudo(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    players: Res<Players>,
+    game: Option<Res<Perudo>>,
+    concealed: Query<Entity, With<Concealed>>,
+) {
+    if *mode == GameMode::Perudo {
+        commands.insert_resource(Perudo::new(&players));
+    } else if game.is_some() {
+        commands.remove_resource::<Perudo>();
+        for entity in concealed.iter() {
+            commands.entity(entity).remove::<Concealed>();
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn play_perudo(
+    mut commands: Commands,
+    mut game: ResMut<Perudo>,
+    mut players: ResMut<Players>,
+    mut count_die: ResMut<CountDie>,
+    time: Res<Time>,
+    roll: Single<&Roll>,
+    mut dice: Query<
+        (
+            Entity,
+            &mut LinearVelocity,
+            &mut AngularVelocity,
+            Has<Counted>,
+        ),
+        With<Die>,
+    >,
+    concealed: Query<Entity, With<Concealed>>,
+) {
+    if game.seats.changed(&players) {
+        game.restart(&players);
+        count_die.0 = false;
+    }
+    let values = |player: Option<usize>| {
+        roll.results
+            .iter()
+            .filter(|(_, owner, _)| player.is_none_or(|player| *owner == player))
+            .filter_map(|(_, _, label)| label.value)
+            .map(|value| value as u8)
+            .collect::<Vec<_>>()
+    };
+    let total = game.dice.iter().sum::<usize>();
+    if game.phase == Phase::Settling && dice.iter().len() != total {
+        game.phase = Phase::Shaking;
+        game.deal = true;
+        count_die.0 = false;
+    }
+    if game.phase == Phase::Settling && dice.iter().len() == total && dice.iter().all(|die| die.3) {
+        game.phase = Phase::Bidding;
+        count_die.0 = false;
+    }
+
+    let active = players.active;
+    if game.action.is_none()
+        && game.computer.contains(&players.active().name)
+        && matches!(game.phase, Phase::Shaking | Phase::Bidding)
+        && game.thinking.tick(time.delta()).just_finished()
+    {
+        game.action = Some(if game.phase == Phase::Shaking {
+            Action::Shake
+        } else {
+            let own = values(Some(active));
+            let previous = game.bid.map(|(_, bid)| bid);
+            computer_move(&own, total - own.len(), previous)
+        });
+    }
+
+    let Some(action) = game.action.take() else {
+        return;
+    };
+    match (game.phase, action) {
+        (Phase::Shaking, Action::Shake) if dice.iter().len() != total => game.deal = true,
+        (Phase::Shaking, Action::Shake) => {
+            let shaken = dice
+                .iter_mut()
+                .map(|(entity, linear_velocity, angular_velocity, _)| {
+                    (entity, linear_velocity, angular_velocity)
+                });
+            shake_dice(&mut commands, shaken);
+            count_die.0 = true;
+            game.phase = Phase::Settling;
+        }
+        (Phase::Bidding, Action::Bid(bid))
+            if bid.count <= total && bid.raises(game.bid.map(|(_, bid)| bid).as_ref()) =>
+        {
+            game.bid = Some((active, bid));
+            pass_turn(&mut players, &game.dice);
+        }
+        (Phase::Bidding, Action::Challenge) => {
+            let Some((bidder, bid)) = game.bid else {
+                return;
+            };
+            let found = matching(&values(None), bid.face);
+            let loser = if found >= bid.count { active } else { bidder };
+            game.dice[loser] -= 1;
+            game.outcome = format!(
+                "{} bid {}, there are {found}, {} loses a die",
+                players.list[bidder].name,
+                bid.name(),
+                players.list[loser].name,
+            );
+            for entity in concealed.iter() {
+                commands.entity(entity).remove::<Concealed>();
+            }
+            let left = (0..game.dice.len())
+                .filter(|player| game.dice[*player] > 0)
+                .collect::<Vec<_>>();
+            game.phase = if let [winner] = left[..] {
+                players.list[winner].score += 1;
+                Phase::Over { winner }
+            } else {
+                Phase::Revealed { loser }
+            };
+        }
+        // the loser starts the next round, or the next player if the loser is out
+        (Phase::Revealed { loser }, Action::NextRound) => {
+            players.active = loser;
+            if game.dice[loser] == 0 {
+                pass_turn(&mut players, &game.dice);
+            }
+            game.bid = None;
+            game.phase = Phase::Shaking;
+            game.deal = true;
+        }
+        (Phase::Over { .. }, Action::NewGame) => game.restart(&players),
+        _ => {}
+    }
+}
+
+// every player gets the dice they have left in their own cup
+fn deal_dice(
+    mut game: ResMut<Perudo>,
+    mut spawner: DieSpawner,
+    mut roll: Single<&mut Roll>,
+    dice: Query<Entity, With<Die>>,
+    cups: Query<(&Owner, &Transform), With<Cup>>,
+) {
+    if !game.deal || !clear_for_deal(&mut spawner, &mut roll, &dice, cups.iter().len()) {
+        return;
+    }
+    game.deal = false;
+    for (owner, cup) in cups.iter() {
+        let count = game.dice.get(owner.0).copied().unwrap_or_default();
+        let style = spawner.players.list[owner.0].style;
+        for spot in SPOTS.iter().take(count) {
+            let transform = Transform::from_translation(cup.translation + *spot);
+            if let Some(entity) = spawner.spawn_with(transform, DieFaces::numbered()) {
+                spawner
+                    .commands
+                    .entity(entity)
+                    .insert((Concealed, Owner(owner.0), style));
+            }
+        }
+    }
+}
+
+fn perudo_panel(
+    mut contexts: EguiContexts,
+    mut game: ResMut<Perudo>,
+    players: Res<Players>,
+    mut choice: Local<Option<Bid>>,
+) {
+    let active = players.active().name.as_str();
+    let total = game.dice.iter().sum::<usize>();
+    let previous = game.bid;
+    let mut computer = game.computer.clone();
+    let mut action = None;
+    egui::Window::new("Perudo").show(contexts.ctx_mut(), |ui| {
+        egui::Grid::new("perudo").show(ui, |ui| {
+            for (player, dice) in players.list.iter().zip(&game.dice) {
+                ui.label(player.name.as_str());
+                ui.label(format!("{dice} dice"));
+                let mut played = computer.contains(&player.name);
+                if ui.checkbox(&mut played, "computer").changed() {
+                    if played {
+                        computer.insert(player.name.clone());
+                    } else {
+                        computer.remove(&player.name);
+                    }
+                }
+                ui.end_row();
+            }
+        });
+        if let Some((bidder, bid)) = previous
+            && let Some(bidder) = players.list.get(bidder)
+        {
+            ui.label(format!("{} bid {}", bidder.name, bid.name()));
+        }
+        let thinking = computer.contains(active);
+        match game.phase {
+            Phase::Shaking if thinking => {
+                ui.label(format!("{active} shakes the cups"));
+            }
+            Phase::Shaking => {
+                ui.label(format!("{active}, shake the cups"));
+                if ui.button("Shake").clicked() {
+                    action = Some(Action::Shake);
+                }
+            }
+            Phase::Settling => {
+                ui.label("the dice are settling");
+            }
+            Phase::Bidding if thinking => {
+                ui.label(format!("{active} is thinking"));
+            }
+            Phase::Bidding => {
+                ui.label(format!("{active}, hold P to peek under your cup"));
+                let previous = previous.map(|(_, bid)| bid);
+                let mut bid = choice
+                    .filter(|bid| bid.raises(previous.as_ref()))
+                    .or_else(|| {
+                        previous.map(|previous| Bid {
+                            count: previous.count + 1,
+                            ..previous
+                        })
+                    })
+                    .unwrap_or(Bid { count: 1, face: 2 });
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut bid.count).range(1..=total.max(1)));
+                    egui::ComboBox::from_label("× face")
+                        .selected_text(bid.face.to_string())
+                        .show_ui(ui, |ui| {
+                            for face in 1..=6 {
+                                ui.selectable_value(&mut bid.face, face, face.to_string());
+                            }
+                        });
+                });
+                ui.horizontal(|ui| {
+                    let valid = bid.count <= total && bid.raises(previous.as_ref());
+                    if ui.add_enabled(valid, egui::Button::new("Bid")).clicked() {
+                        action = Some(Action::Bid(bid));
+                    }
+                    if ui
+                        .add_enabled(previous.is_some(), egui::Button::new("Call liar"))
+                        .clicked()
+                    {
+                        action = Some(Action::Challenge);
+                    }
+                });
+                *choice = Some(bid);
+            }
+            Phase::Revealed { .. } => {
+                ui.label(game.outcome.as_str());
+                if ui.button("Next round").clicked() {
+                    action = Some(Action::NextRound);
+                }
+            }
+            Phase::Over { winner } => {
+                ui.label(game.outcome.as_str());
+                if let Some(winner) = players.list.get(winner) {
+                    ui.label(format!("{} wins", winner.name));
+                }
+                if ui.button("New game").clicked() {
+                    action = Some(Action::NewGame);
+                }
+            }
+        }
+    });
+    if computer != game.computer {
+        game.computer = computer;
+    }
+    if action.is_some() {
+        game.action = action;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bid(count: usize, face: u8) -> Bid {
+        Bid { count, face }
+    }
+
+    #[test]
+    fn bids_are_raised() {
+        assert!(bid(1, 2).raises(None));
+        assert!(!bid(3, 1).raises(None));
+        assert!(!bid(0, 4).raises(None));
+        assert!(!bid(2, 7).raises(None));
+        let previous = bid(4, 3);
+        assert!(bid(4, 5).raises(Some(&previous)));
+        assert!(bid(5, 2).raises(Some(&previous)));
+        assert!(!bid(4, 3).raises(Some(&previous)));
+        assert!(!bid(4, 2).raises(Some(&previous)));
+        // ones need half as many, rounded up, and twice as many plus one to leave them
+        assert!(bid(2, 1).raises(Some(&previous)));
+        assert!(!bid(1, 1).raises(Some(&bid(3, 6))));
+        assert!(bid(2, 1).raises(Some(&bid(3, 6))));
+        assert!(!bid(4, 6).raises(Some(&bid(2, 1))));
+        assert!(bid(5, 2).raises(Some(&bid(2, 1))));
+        assert!(bid(3, 1).raises(Some(&bid(2, 1))));
+    }
+
+    #[test]
+    fn ones_are_wild() {
+        let values = [1, 5, 5, 1, 3, 6];
+        assert_eq!(matching(&values, 5), 4);
+        assert_eq!(matching(&values, 1), 2);
+        assert_eq!(matching(&values, 4), 2);
+    }
+
+    #[test]
+    fn computers_bid_what_they_expect() {
+        let own = [5, 5, 5, 1, 2];
+        assert_eq!(computer_move(&own, 5, None), Action::Bid(bid(1, 5)));
+        assert_eq!(
+            computer_move(&own, 5, Some(bid(3, 5))),
+            Action::Bid(bid(4, 5))
+        );
+        assert_eq!(computer_move(&own, 5, Some(bid(10, 5))), Action::Challenge);
+    }
+}
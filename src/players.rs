@@ -0,0 +1,378 @@
+use crate::style::{DieStyle, Finish};
+use bevy::prelude::*;
+
+// the player a cup or a die belongs to, as an index into Players
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Owner(pub usize);
+
+// the cup that follows the mouse, there is exactly one
+#[derive(Component)]
+pub struct ActiveCup;
+
+#[derive(Clone)]
+pub struct Player {
+    pub name: String,
+    // the style new dice of this player get, edited by the style picker while the player is active
+    pub style: DieStyle,
+    // the totals of all completed rolls
+    pub score: i32,
+    // joined over the network, so the cup is not controlled from this machine
+    pub remote: bool,
+}
+
+#[derive(Resource)]
+pub struct Players {
+    pub list: Vec<Player>,
+    pub active: usize,
+}
+
+// body and pip colors, the pips contrast with the body, in bytes as the style picker rounds to them
+const PLAYER_COLORS: [(Color, Color); 6] = [
+    (Color::srgb_u8(255, 255, 255), Color::srgb_u8(0, 0, 0)),
+    (Color::srgb_u8(204, 26, 26), Color::srgb_u8(255, 255, 255)),
+    (Color::srgb_u8(26, 77, 204), Color::srgb_u8(255, 255, 255)),
+    (Color::srgb_u8(26, 153, 51), Color::srgb_u8(255, 255, 255)),
+    (Color::srgb_u8(242, 204, 26), Color::srgb_u8(0, 0, 0)),
+    (Color::srgb_u8(128, 51, 179), Color::srgb_u8(255, 255, 255)),
+];
+
+impl Players {
+    pub const MAX: usize = PLAYER_COLORS.len();
+
+    pub fn new(count: usize) -> Result<Self, String> {
+        if !(1..=Players::MAX).contains(&count) {
+            return Err(format!(
+                "there can be 1 to {} players, got {count}",
+                Players::MAX
+            ));
+        }
+        let mut players = Players {
+            list: vec![],
+            active: 0,
+        };
+        for i in 0..count {
+            players.add(&format!("Player {}", i + 1))?;
+        }
+        Ok(players)
+    }
+
+    // new players get the first color nobody else has
+    pub fn add(&mut self, name: &str) -> Result<(), String> {
+        let name = name.trim();
+        if name.is_empty() || name.contains('\n') {
+            return Err(String::from("a name needs a single line of text"));
+        }
+        // the line of such a player would be read back as a comment
+        if name.starts_with('#') {
+            return Err(String::from("a name cannot start with #"));
+        }
+        if self.list.iter().any(|player| player.name == name) {
+            return Err(format!("there is already a player called {name}"));
+        }
+        let (body, pips) = PLAYER_COLORS
+            .iter()
+            .find(|(body, _)| self.list.iter().all(|player| player.style.body != *body))
+            .ok_or_else(|| format!("there can be at most {} players", Players::MAX))?;
+        self.list.push(Player {
+            name: String::from(name),
+            style: DieStyle {
+                body: *body,
+                pips: *pips,
+                finish: Finish::Resin,
+            },
+            score: 0,
+            remote: false,
+        });
+        Ok(())
+    }
+
+    // the turn stays with the same player, unless it is the one removed
+    pub fn remove(&mut self, player: usize) -> Result<(), String> {
+        if self.list.len() == 1 {
+            return Err(String::from("the last player cannot leave"));
+        }
+        self.list.remove(player);
+        if player < self.active {
+            self.active -= 1;
+        } else if self.active == self.list.len() {
+            self.active = 0;
+        }
+        Ok(())
+    }
+
+    // swaps a player with the one before, who then plays after them
+    pub fn move_up(&mut self, player: usize) {
+        if player == 0 || player >= self.list.len() {
+            return;
+        }
+        self.list.swap(player - 1, player);
+        if self.active == player {
+            self.active -= 1;
+        } else if self.active == player - 1 {
+            self.active += 1;
+        }
+    }
+
+    // the first line holds whose turn it is, the others `<name> = <score>` in turn order, followed
+    // by the body and pip colors and the finish of the player's dice; players without a style get
+    // the next free color
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        let active = lines
+            .next()
+            .and_then(|line| line.strip_prefix("turn"))
+            .and_then(|line| line.trim().strip_prefix('='))
+            .ok_or("expected turn = <player> first")?;
+        let active = active
+            .trim()
+            .parse()
+            .map_err(|_| format!("turn must be a number, got {active}"))?;
+        let mut players = Players {
+            list: vec![],
+            active,
+        };
+        for line in lines {
+            // the score comes last, so that names may contain =
+            let (name, values) = line
+                .rsplit_once('=')
+                .ok_or_else(|| format!("expected <name> = <score>, got {line}"))?;
+            players.add(name)?;
+            let player = players.list.last_mut().expect("just added");
+            let name = name.trim();
+            match values.split_whitespace().collect::<Vec<_>>()[..] {
+                [score] => player.score = parse_score(name, score)?,
+                [score, body, pips, finish] => {
+                    player.score = parse_score(name, score)?;
+                    let color = |value: &str| {
+                        Srgba::hex(value)
+                            .map(Color::from)
+                            .map_err(|_| format!("the colors of {name} must be hex, got {value}"))
+                    };
+                    player.style = DieStyle {
+                        body: color(body)?,
+                        pips: color(pips)?,
+                        finish: Finish::parse(finish)?,
+                    };
+                }
+                _ => {
+                    return Err(format!(
+                        "expected <name> = <score> [<body> <pips> <finish>], got {line}"
+                    ));
+                }
+            }
+        }
+        if players.list.is_empty() {
+            return Err(String::from("there are no players"));
+        }
+        if players.active >= players.list.len() {
+            return Err(format!("there is no player {}", players.active));
+        }
+        Ok(players)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("turn = {}\n", self.active);
+        for player in &self.list {
+            let style = player.style;
+            text.push_str(&format!(
+                "{} = {} {} {} {}\n",
+                player.name,
+                player.score,
+                style.body.to_srgba().to_hex(),
+                style.pips.to_srgba().to_hex(),
+                style.finish.name()
+            ));
+        }
+        text
+    }
+
+    // the players without the guests who joined over the network, none if nobody plays on this
+    // machine; the turn goes to the next local player if a guest has it
+    pub fn local(&self) -> Option<Players> {
+        let list = self
+            .list
+            .iter()
+            .filter(|player| !player.remote)
+            .cloned()
+            .collect::<Vec<_>>();
+        if list.is_empty() {
+            return None;
+        }
+        let before = self.list[..self.active]
+            .iter()
+            .filter(|player| !player.remote)
+            .count();
+        Some(Players {
+            active: before % list.len(),
+            list,
+        })
+    }
+
+    pub fn active(&self) -> &Player {
+        &self.list[self.active]
+    }
+
+    pub fn next(&mut self) {
+        self.active = (self.active + 1) % self.list.len();
+    }
+
+    // cups stand evenly spaced around the center of the table
+    pub fn cup_position(&self, player: usize) -> Vec3 {
+        if self.list.len() == 1 {
+            return Vec3::new(2.0, 1.2, 0.0);
+        }
+        let angle = player as f32 / self.list.len() as f32 * std::f32::consts::TAU;
+        Vec3::new(4.0 * angle.cos(), 1.2, 4.0 * angle.sin())
+    }
+}
+
+fn parse_score(name: &str, score: &str) -> Result<i32, String> {
+    score
+        .parse()
+        .map_err(|_| format!("the score of {name} must be a number, got {score}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn player_count_is_limited() {
+        assert!(Players::new(0).is_err());
+        assert!(Players::new(Players::MAX + 1).is_err());
+        for count in 1..=Players::MAX {
+            assert_eq!(Players::new(count).unwrap().list.len(), count);
+        }
+    }
+
+    #[test]
+    fn turns_go_around_the_table() {
+        let mut players = Players::new(3).unwrap();
+        let mut turns = vec![];
+        for _ in 0..4 {
+            turns.push(players.active);
+            players.next();
+        }
+        assert_eq!(turns, [0, 1, 2, 0]);
+        assert_eq!(players.active().name, "Player 2");
+    }
+
+    #[test]
+    fn the_turn_follows_the_player() {
+        let mut players = Players::new(4).unwrap();
+        players.active = 2;
+        players.move_up(2);
+        assert_eq!(players.active, 1);
+        players.move_up(2);
+        assert_eq!(players.active, 2);
+        players.remove(0).unwrap();
+        assert_eq!(players.active().name, "Player 3");
+        // the turn passes on when the active player leaves
+        players.remove(1).unwrap();
+        assert_eq!(players.active().name, "Player 4");
+        players.remove(1).unwrap();
+        assert_eq!(players.active().name, "Player 2");
+        assert!(players.remove(0).is_err());
+        let mut players = Players::new(3).unwrap();
+        players.active = 2;
+        players.remove(2).unwrap();
+        assert_eq!(players.active().name, "Player 1");
+    }
+
+    #[test]
+    fn new_players_get_a_free_color() {
+        let mut players = Players::new(3).unwrap();
+        let second = players.list[1].style;
+        players.remove(1).unwrap();
+        players.add("Latecomer").unwrap();
+        assert_eq!(players.list[2].style, second);
+        assert!(players.add("Latecomer").is_err());
+        assert!(players.add(" ").is_err());
+        for i in 0..Players::MAX - 3 {
+            assert!(players.add(&format!("Guest {i}")).is_ok());
+        }
+        assert!(players.add("One too many").is_err());
+    }
+
+    #[test]
+    fn sessions_survive_a_round_trip() {
+        let mut players = Players::new(3).unwrap();
+        players.add("A = B").unwrap();
+        players.list[1].score = 17;
+        players.list[3].score = -4;
+        players.active = 3;
+        let parsed = Players::parse(&players.to_text()).unwrap();
+        assert_eq!(parsed.to_text(), players.to_text());
+        assert_eq!(parsed.list[3].name, "A = B");
+        assert_eq!(parsed.list[3].style, players.list[3].style);
+        // styles are kept, sessions saved before them get the next free color
+        players.list[0].style = DieStyle {
+            body: Color::srgb_u8(1, 2, 3),
+            pips: Color::srgb_u8(250, 128, 0),
+            finish: Finish::Glass,
+        };
+        let parsed = Players::parse(&players.to_text()).unwrap();
+        assert_eq!(parsed.list[0].style, players.list[0].style);
+        let parsed =
+            Players::parse("turn = 0\nAlice = 3\nBob = 4 #102030 #FFFFFF Metal\n").unwrap();
+        assert_eq!(parsed.list[0].style, Players::new(1).unwrap().list[0].style);
+        assert_eq!(parsed.list[1].style.finish, Finish::Metal);
+        assert!(players.add("#1").is_err());
+        let commented = format!("# saved by hand\n{}", players.to_text());
+        assert_eq!(
+            Players::parse(&commented).unwrap().to_text(),
+            players.to_text()
+        );
+        for text in [
+            "",
+            "turn = 0\n",
+            "turn = 1\nAlice = 3\n",
+            "Alice = 3\n",
+            "turn = 0\nAlice\n",
+            "turn = 0\nAlice = 3 #FFFFFF\n",
+            "turn = 0\nAlice = 3 white black Resin\n",
+            "turn = 0\nAlice = 3 #FFFFFF #000000 Stone\n",
+        ] {
+            assert!(Players::parse(text).is_err(), "{text:?}");
+        }
+    }
+
+    #[test]
+    fn guests_are_not_local_players() {
+        let mut players = Players::new(3).unwrap();
+        players.list[1].remote = true;
+        let names = |players: &Players| {
+            let names = players.list.iter().map(|player| player.name.as_str());
+            names.collect::<Vec<_>>().join(", ")
+        };
+        for (active, local) in [(0, 0), (1, 1), (2, 1)] {
+            players.active = active;
+            let local_players = players.local().unwrap();
+            assert_eq!(names(&local_players), "Player 1, Player 3");
+            assert_eq!(local_players.active, local);
+        }
+        players.list[0].remote = true;
+        players.list[2].remote = true;
+        assert!(players.local().is_none());
+    }
+
+    #[test]
+    fn cups_stand_apart_on_the_table() {
+        for count in 2..=Players::MAX {
+            let players = Players::new(count).unwrap();
+            let positions = (0..count)
+                .map(|player| players.cup_position(player))
+                .collect::<Vec<_>>();
+            for (i, a) in positions.iter().enumerate() {
+                // the table has a radius of 6
+                assert!(a.xz().length() < 5.0);
+                for b in &positions[i + 1..] {
+                    assert!(a.distance(*b) > 2.0, "{count}: {a} {b}");
+                }
+            }
+        }
+    }
+}
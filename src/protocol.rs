@@ -0,0 +1,453 @@
+use crate::style::{DieStyle, Finish};
+use bevy::prelude::*;
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+
+// one message per line, the words are separated by spaces and free text always comes last
+#[derive(Clone, PartialEq, Debug)]
+pub enum Message {
+    // client to host
+    Join(String),
+    Input {
+        // the point on the table the cup moves above
+        target: Vec3,
+        lift: f32,
+        tilt: bool,
+    },
+    Spawn,
+    // host to client, the table is sent as a snapshot of cups and dice that ends with a frame
+    Welcome(usize),
+    Players(usize),
+    Player {
+        score: i32,
+        style: DieStyle,
+        name: String,
+    },
+    Cup {
+        owner: usize,
+        transform: Transform,
+    },
+    Die {
+        id: u64,
+        owner: usize,
+        transform: Transform,
+        style: DieStyle,
+        faces: String,
+    },
+    Frame,
+    Roll(String),
+}
+
+// a peer that stops reading, or never ends its line, is dropped before its buffer takes over the memory
+const MAX_PENDING: usize = 4 << 20;
+const MAX_LINE: usize = 64 << 10;
+
+// a non-blocking tcp stream, neither reading nor writing ever waits for the other side
+pub struct Connection {
+    stream: TcpStream,
+    received: Vec<u8>,
+    pending: Vec<u8>,
+}
+
+impl Message {
+    pub fn to_line(&self) -> String {
+        let transform = |transform: &Transform| {
+            let (translation, rotation) = (transform.translation, transform.rotation);
+            format!(
+                "{} {} {} {} {} {} {}",
+                translation.x,
+                translation.y,
+                translation.z,
+                rotation.x,
+                rotation.y,
+                rotation.z,
+                rotation.w
+            )
+        };
+        let style = |style: &DieStyle| {
+            format!(
+                "{} {} {}",
+                style.body.to_srgba().to_hex(),
+                style.pips.to_srgba().to_hex(),
+                style.finish.name()
+            )
+        };
+        match self {
+            Message::Join(name) => format!("join {name}"),
+            Message::Input { target, lift, tilt } => {
+                format!(
+                    "input {} {} {} {lift} {}",
+                    target.x, target.y, target.z, *tilt as u8
+                )
+            }
+            Message::Spawn => String::from("spawn"),
+            Message::Welcome(player) => format!("welcome {player}"),
+            Message::Players(active) => format!("players {active}"),
+            Message::Player {
+                score,
+                style: die_style,
+                name,
+            } => format!("player {score} {} {name}", style(die_style)),
+            Message::Cup {
+                owner,
+                transform: cup,
+            } => format!("cup {owner} {}", transform(cup)),
+            Message::Die {
+                id,
+                owner,
+                transform: die,
+                style: die_style,
+                faces,
+            } => format!(
+                "die {id} {owner} {} {} {faces}",
+                transform(die),
+                style(die_style)
+            ),
+            Message::Frame => String::from("frame"),
+            Message::Roll(text) => format!("roll {}", escape(text)),
+        }
+    }
+
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let (kind, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let mut words = Words {
+            line,
+            rest: rest.split(' '),
+        };
+        let message = match kind {
+            "join" => Message::Join(words.text(rest)?),
+            "input" => Message::Input {
+                target: words.vec3()?,
+                lift: words.number()?,
+                tilt: words.number::<u8>()? != 0,
+            },
+            "spawn" => Message::Spawn,
+            "welcome" => Message::Welcome(words.number()?),
+            "players" => Message::Players(words.number()?),
+            "player" => Message::Player {
+                score: words.number()?,
+                style: words.style()?,
+                name: words.remainder()?,
+            },
+            "cup" => Message::Cup {
+                owner: words.number()?,
+                transform: words.transform()?,
+            },
+            "die" => Message::Die {
+                id: words.number()?,
+                owner: words.number()?,
+                transform: words.transform()?,
+                style: words.style()?,
+                faces: words.remainder()?,
+            },
+            "frame" => Message::Frame,
+            "roll" => Message::Roll(unescape(rest)),
+            _ => return Err(format!("unknown message {line}")),
+        };
+        Ok(message)
+    }
+}
+
+// line breaks are the only characters that need escaping, and the escape itself
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some(c) => unescaped.push(c),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+struct Words<'a> {
+    line: &'a str,
+    rest: std::str::Split<'a, char>,
+}
+
+impl<'a> Words<'a> {
+    fn word(&mut self) -> Result<&'a str, String> {
+        self.rest
+            .next()
+            .filter(|word| !word.is_empty())
+            .ok_or_else(|| format!("message is too short: {}", self.line))
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> Result<T, String> {
+        let word = self.word()?;
+        word.parse()
+            .map_err(|_| format!("expected a number, got {word} in {}", self.line))
+    }
+
+    fn vec3(&mut self) -> Result<Vec3, String> {
+        Ok(Vec3::new(self.number()?, self.number()?, self.number()?))
+    }
+
+    fn transform(&mut self) -> Result<Transform, String> {
+        let translation = self.vec3()?;
+        let rotation = Quat::from_xyzw(
+            self.number()?,
+            self.number()?,
+            self.number()?,
+            self.number()?,
+        );
+        Ok(Transform::from_translation(translation).with_rotation(rotation))
+    }
+
+    fn color(&mut self) -> Result<Color, String> {
+        let word = self.word()?;
+        Srgba::hex(word)
+            .map(Color::Srgba)
+            .map_err(|_| format!("expected a color, got {word} in {}", self.line))
+    }
+
+    fn style(&mut self) -> Result<DieStyle, String> {
+        let body = self.color()?;
+        let pips = self.color()?;
+        let word = self.word()?;
+        let finish = Finish::ALL
+            .into_iter()
+            .find(|finish| finish.name() == word)
+            .ok_or_else(|| format!("unknown finish {word} in {}", self.line))?;
+        Ok(DieStyle { body, pips, finish })
+    }
+
+    // free text with spaces, like a name, ends the message
+    fn remainder(&mut self) -> Result<String, String> {
+        let words = self.rest.by_ref().collect::<Vec<_>>();
+        self.text(&words.join(" "))
+    }
+
+    fn text(&self, text: &str) -> Result<String, String> {
+        if text.is_empty() {
+            return Err(format!("message is too short: {}", self.line));
+        }
+        Ok(String::from(text))
+    }
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> std::io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        // snapshots are small and sent every frame, so they are not held back
+        stream.set_nodelay(true)?;
+        Ok(Connection {
+            stream,
+            received: vec![],
+            pending: vec![],
+        })
+    }
+
+    // messages beyond the limit are dropped, the next receive then fails
+    pub fn send(&mut self, message: &Message) {
+        if self.pending.len() > MAX_PENDING {
+            return;
+        }
+        self.pending.extend_from_slice(message.to_line().as_bytes());
+        self.pending.push(b'\n');
+    }
+
+    // whatever the socket does not take now is kept for the next flush
+    pub fn flush(&mut self) -> Result<(), String> {
+        while !self.pending.is_empty() {
+            match self.stream.write(&self.pending) {
+                Ok(0) => return Err(String::from("the connection was closed")),
+                Ok(written) => {
+                    self.pending.drain(..written);
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error.to_string()),
+            }
+        }
+        Ok(())
+    }
+
+    // every complete line that arrived so far, a partial line waits for the rest
+    pub fn receive(&mut self) -> Result<Vec<Message>, String> {
+        if self.pending.len() > MAX_PENDING {
+            return Err(String::from("the other side stopped reading"));
+        }
+        let mut buffer = [0; 4096];
+        // the rest is read on the next call, after the complete lines were taken out
+        while self.received.len() < MAX_PENDING {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return Err(String::from("the connection was closed")),
+                Ok(read) => self.received.extend_from_slice(&buffer[..read]),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error.to_string()),
+            }
+        }
+        let mut messages = vec![];
+        while let Some(end) = self.received.iter().position(|byte| *byte == b'\n') {
+            let line = self.received.drain(..=end).collect::<Vec<_>>();
+            let line = std::str::from_utf8(&line[..end]).map_err(|error| error.to_string())?;
+            messages.push(Message::parse(line)?);
+        }
+        if self.received.len() > MAX_LINE {
+            return Err(String::from("a line is too long"));
+        }
+        Ok(messages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    fn transform() -> Transform {
+        Transform::from_xyz(1.5, -0.25, 1e-7).with_rotation(Quat::from_euler(
+            EulerRot::XYZ,
+            0.1,
+            2.0,
+            -3.0,
+        ))
+    }
+
+    fn messages() -> Vec<Message> {
+        let style = DieStyle {
+            body: Color::srgb_u8(204, 26, 26),
+            pips: Color::srgb_u8(255, 255, 255),
+            finish: Finish::Glass,
+        };
+        vec![
+            Message::Join(String::from("Ada Lovelace")),
+            Message::Input {
+                target: Vec3::new(0.1, 1.2, -3.0),
+                lift: -1.0,
+                tilt: true,
+            },
+            Message::Spawn,
+            Message::Welcome(3),
+            Message::Players(1),
+            Message::Player {
+                score: -12,
+                style,
+                name: String::from("A = B"),
+            },
+            Message::Cup {
+                owner: 2,
+                transform: transform(),
+            },
+            Message::Die {
+                id: 4294967301,
+                owner: 0,
+                transform: transform(),
+                style,
+                faces: String::from("FATE"),
+            },
+            Message::Frame,
+            Message::Roll(String::from("> Ada: + - = 0\nBob \\ 1:")),
+        ]
+    }
+
+    #[test]
+    fn messages_survive_a_round_trip() {
+        for message in messages() {
+            let line = message.to_line();
+            assert!(!line.contains('\n'), "{line}");
+            assert_eq!(Message::parse(&line), Ok(message), "{line}");
+        }
+    }
+
+    #[test]
+    fn broken_messages_are_rejected() {
+        for line in [
+            "",
+            "jump",
+            "join",
+            "welcome you",
+            "input 1 2 3 0",
+            "cup 0 1 2 3",
+            "player 3 #ffffff #000000 Plastic Ada",
+            "player 3 white #000000 Resin Ada",
+            "die 1 0 0 0 0 0 0 0 1 #ffffff #000000 Resin",
+        ] {
+            assert!(Message::parse(line).is_err(), "{line}");
+        }
+    }
+
+    fn connected() -> (Connection, Connection) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = Connection::new(TcpStream::connect(address).unwrap()).unwrap();
+        let host = Connection::new(listener.accept().unwrap().0).unwrap();
+        (client, host)
+    }
+
+    #[test]
+    fn messages_arrive_over_tcp() {
+        let (mut client, mut host) = connected();
+        // enough messages to need several reads and a partial line in between
+        let mut sent = vec![];
+        for _ in 0..200 {
+            for message in messages() {
+                client.send(&message);
+                sent.push(message);
+            }
+        }
+        let mut received = vec![];
+        for _ in 0..1000 {
+            client.flush().unwrap();
+            received.extend(host.receive().unwrap());
+            if received.len() == sent.len() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(received, sent);
+
+        drop(client);
+        let mut closed = false;
+        for _ in 0..1000 {
+            if host.receive().is_err() {
+                closed = true;
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!(closed);
+    }
+
+    #[test]
+    fn stalled_peers_are_dropped() {
+        let (mut client, mut host) = connected();
+        client
+            .pending
+            .extend(std::iter::repeat_n(b'x', MAX_LINE + 1));
+        let mut dropped = false;
+        for _ in 0..1000 {
+            client.flush().unwrap();
+            if host.receive().is_err() {
+                dropped = true;
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!(dropped, "a line that never ends");
+
+        // the client never reads, so the snapshots pile up on the host
+        let (_client, mut host) = connected();
+        let message = Message::Roll("x".repeat(1000));
+        let dropped = (0..100_000).any(|_| {
+            host.send(&message);
+            host.flush().unwrap();
+            host.receive().is_err()
+        });
+        assert!(dropped, "a peer that stopped reading");
+        assert!(host.pending.len() <= MAX_PENDING + message.to_line().len() + 1);
+    }
+}
@@ -0,0 +1,208 @@
+use crate::game::GameMode;
+use crate::players::Players;
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{EguiContexts, egui};
+use std::fs;
+use std::path::PathBuf;
+
+// the players, their scores and whose turn it is, kept across restarts
+#[derive(Resource)]
+pub struct SessionFile {
+    path: Option<PathBuf>,
+    // what was last read or written, so that unchanged sessions are not written again
+    saved: String,
+}
+
+impl SessionFile {
+    // saved next to the other settings, in $XDG_CONFIG_HOME/dice/session
+    pub fn from_env() -> Self {
+        let path = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .map(|dir| dir.join("dice").join("session"));
+        SessionFile {
+            path,
+            saved: String::new(),
+        }
+    }
+
+    // a session that cannot be read is replaced with a warning
+    pub fn load(&mut self) -> Option<Players> {
+        let path = self.path.as_ref()?;
+        let text = fs::read_to_string(path).ok()?;
+        match Players::parse(&text) {
+            Ok(players) => {
+                self.saved = text;
+                Some(players)
+            }
+            Err(error) => {
+                warn!("could not load the session {path:?}: {error}");
+                None
+            }
+        }
+    }
+
+    fn save(&mut self, players: &Players) -> Result<(), String> {
+        let text = players.to_text();
+        if text == self.saved {
+            return Ok(());
+        }
+        let path = self.path.as_ref().ok_or("there is no config directory")?;
+        path.parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(path, &text))
+            .map_err(|error| error.to_string())?;
+        self.saved = text;
+        Ok(())
+    }
+}
+
+// guests are only seated for as long as they are connected, so they are not saved
+pub fn save_session(mut session: ResMut<SessionFile>, players: Res<Players>) {
+    let Some(players) = players.local() else {
+        return;
+    };
+    if let Err(error) = session.save(&players) {
+        warn!("could not save the session: {error}");
+    }
+}
+
+pub fn players_panel(
+    mut contexts: EguiContexts,
+    mut players: ResMut<Players>,
+    mut mode: ResMut<GameMode>,
+    mut name: Local<String>,
+    mut error: Local<Option<String>>,
+) {
+    // players are only written on clicks, so that the seating is not checked every frame
+    let mut remove = None;
+    let mut move_up = None;
+    let mut next = false;
+    let mut add = false;
+    let mut reset = false;
+    let mut game = *mode;
+    egui::Window::new("Players").show(contexts.ctx_mut(), |ui| {
+        egui::ComboBox::from_label("Game")
+            .selected_text(game.name())
+            .show_ui(ui, |ui| {
+                for mode in GameMode::ALL {
+                    ui.selectable_value(&mut game, mode, mode.name());
+                }
+            });
+        egui::Grid::new("players").show(ui, |ui| {
+            for (i, player) in players.list.iter().enumerate() {
+                ui.label(if i == players.active { ">" } else { "" });
+                let [red, green, blue] = player.style.body.to_srgba().to_u8_array_no_alpha();
+                ui.colored_label(
+                    egui::Color32::from_rgb(red, green, blue),
+                    player.name.as_str(),
+                );
+                ui.label(player.score.to_string());
+                if ui.add_enabled(i > 0, egui::Button::new("Up")).clicked() {
+                    move_up = Some(i);
+                }
+                if ui
+                    .add_enabled(players.list.len() > 1, egui::Button::new("Remove"))
+                    .clicked()
+                {
+                    remove = Some(i);
+                }
+                ui.end_row();
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut *name);
+            add = ui.button("Add player").clicked();
+        });
+        ui.horizontal(|ui| {
+            next = ui.button("Next turn").clicked();
+            reset = ui.button("Reset scores").clicked();
+        });
+        if let Some(error) = error.as_ref() {
+            ui.colored_label(egui::Color32::RED, error.as_str());
+        }
+    });
+    if game != *mode {
+        *mode = game;
+    }
+    let result = if let Some(player) = remove {
+        players.remove(player)
+    } else if let Some(player) = move_up {
+        players.move_up(player);
+        Ok(())
+    } else if add {
+        players.add(&name).map(|_| name.clear())
+    } else if next {
+        players.next();
+        Ok(())
+    } else if reset {
+        for player in players.list.iter_mut() {
+            player.score = 0;
+        }
+        Ok(())
+    } else {
+        return;
+    };
+    *error = result.err();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::style::{DieStyle, Finish};
+
+    fn temporary_session(name: &str) -> SessionFile {
+        let dir =
+            std::env::temp_dir().join(format!("dice-session-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        SessionFile {
+            path: Some(dir.join("session")),
+            saved: String::new(),
+        }
+    }
+
+    #[test]
+    fn sessions_are_saved_and_loaded() {
+        let mut session = temporary_session("round-trip");
+        let mut players = Players::new(3).unwrap();
+        players.list[1].score = 12;
+        players.list[2].style = DieStyle {
+            body: Color::srgb_u8(10, 20, 30),
+            pips: Color::srgb_u8(200, 100, 0),
+            finish: Finish::Wood,
+        };
+        players.active = 2;
+        session.save(&players).unwrap();
+        // a new start reads what the last one saved
+        let mut restarted = SessionFile {
+            path: session.path.clone(),
+            saved: String::new(),
+        };
+        let loaded = restarted.load().unwrap();
+        assert_eq!(loaded.active, 2);
+        for (loaded, player) in loaded.list.iter().zip(&players.list) {
+            assert_eq!(loaded.name, player.name);
+            assert_eq!(loaded.score, player.score);
+            assert_eq!(loaded.style, player.style);
+        }
+    }
+
+    #[test]
+    fn missing_and_corrupt_sessions_are_not_loaded() {
+        let mut session = temporary_session("corrupt");
+        assert!(session.load().is_none());
+        let path = session.path.clone().unwrap();
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "turn = 5\nAlice = lots\n").unwrap();
+        assert!(session.load().is_none());
+        // the corrupt file is replaced by the next save
+        session.save(&Players::new(1).unwrap()).unwrap();
+        assert_eq!(session.load().unwrap().list.len(), 1);
+        let mut session = SessionFile {
+            path: None,
+            saved: String::new(),
+        };
+        assert!(session.load().is_none());
+        assert!(session.save(&Players::new(1).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,296 @@
+use crate::die::Die;
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{EguiContexts, egui};
+
+// decides when a die has come to rest, which is when its value is read
+pub struct SettlePlugin;
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum SettleMode {
+    // avian puts dice to sleep once their velocities stay below the thresholds
+    Native,
+    // dice are put to sleep once their pose stops changing
+    #[default]
+    Heuristic,
+}
+
+#[derive(Resource, Clone, Copy, PartialEq, Debug)]
+pub struct SettleSettings {
+    pub mode: SettleMode,
+    // combined translation and rotation change from the last resting pose that counts as motion
+    pub threshold: f32,
+    // velocities below which avian considers a body resting, used in both modes
+    pub linear_threshold: f32,
+    pub angular_threshold: f32,
+    // seconds a die has to rest before it is put to sleep
+    pub time: f32,
+    pub overlay: bool,
+}
+
+#[derive(Component, Default)]
+pub struct AutoSleep {
+    translation: Vec3,
+    rotation: Vec3,
+    // seconds since the pose last changed
+    resting: f32,
+    // seconds since the die was spawned or woken up
+    awake: f32,
+}
+
+// how long dice took from being spawned or woken up until they slept
+#[derive(Resource, Default)]
+pub struct SettleStats {
+    times: Vec<f32>,
+}
+
+impl Plugin for SettlePlugin {
+    fn build(&self, app: &mut App) {
+        // detection runs in step with the physics, so that dice settle alike at any frame rate
+        app.init_resource::<SettleSettings>()
+            .init_resource::<SettleStats>()
+            .add_systems(
+                Update,
+                configure_sleeping.run_if(resource_changed::<SettleSettings>),
+            )
+            .add_systems(FixedUpdate, (detect_sleep, record_settle_times).chain());
+    }
+}
+
+impl SettleMode {
+    pub const ALL: [SettleMode; 2] = [SettleMode::Native, SettleMode::Heuristic];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SettleMode::Native => "native",
+            SettleMode::Heuristic => "heuristic",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        SettleMode::ALL
+            .into_iter()
+            .find(|mode| mode.name() == value)
+            .ok_or_else(|| format!("unknown settle mode {value}, expected native or heuristic"))
+    }
+}
+
+impl Default for SettleSettings {
+    fn default() -> Self {
+        SettleSettings {
+            mode: SettleMode::default(),
+            threshold: 0.1,
+            linear_threshold: 0.15,
+            angular_threshold: 0.15,
+            time: 1.0,
+            overlay: false,
+        }
+    }
+}
+
+impl AutoSleep {
+    // returns the seconds the die has been resting
+    fn update(&mut self, translation: Vec3, rotation: Vec3, delta: f32, threshold: f32) -> f32 {
+        let changed =
+            (self.translation - translation).length() + (self.rotation - rotation).length();
+        if changed > threshold {
+            self.translation = translation;
+            self.rotation = rotation;
+            self.resting = 0.0;
+        } else {
+            self.resting += delta;
+        }
+        self.awake += delta;
+        self.resting
+    }
+}
+
+impl SettleStats {
+    pub fn record(&mut self, time: f32) {
+        self.times.push(time);
+    }
+
+    pub fn clear(&mut self) {
+        self.times.clear();
+    }
+
+    pub fn count(&self) -> usize {
+        self.times.len()
+    }
+
+    pub fn mean(&self) -> Option<f32> {
+        (!self.times.is_empty()).then(|| self.times.iter().sum::<f32>() / self.times.len() as f32)
+    }
+
+    // nearest rank, so the result is always one of the recorded times
+    pub fn percentile(&self, percent: f32) -> Option<f32> {
+        let mut times = self.times.clone();
+        times.sort_by(f32::total_cmp);
+        let rank = (percent / 100.0 * times.len() as f32).ceil() as usize;
+        times.get(rank.clamp(1, times.len().max(1)) - 1).copied()
+    }
+}
+
+// avian keeps its own sleeping in both modes, the heuristic only puts dice to sleep earlier
+fn configure_sleeping(mut commands: Commands, settings: Res<SettleSettings>) {
+    commands.insert_resource(DeactivationTime(settings.time));
+    commands.insert_resource(SleepingThreshold {
+        linear: settings.linear_threshold,
+        angular: settings.angular_threshold,
+    });
+}
+
+fn detect_sleep(
+    mut commands: Commands,
+    settings: Res<SettleSettings>,
+    mut query: Query<(Entity, &Transform, &mut AutoSleep), Without<Sleeping>>,
+    time: Res<Time>,
+) {
+    for (entity, transform, mut auto_sleep) in query.iter_mut() {
+        let resting = auto_sleep.update(
+            transform.translation,
+            transform.rotation.to_scaled_axis(),
+            time.delta_secs(),
+            settings.threshold,
+        );
+        // sleeping dice are woken by avian as soon as something pushes them
+        if settings.mode == SettleMode::Heuristic && resting > settings.time {
+            commands.entity(entity).insert(Sleeping);
+        }
+    }
+}
+
+// the awake time is restarted whenever a die wakes up, so each sleep is recorded once
+fn record_settle_times(
+    mut stats: ResMut<SettleStats>,
+    mut slept: Query<&mut AutoSleep, (With<Sleeping>, Added<Sleeping>)>,
+    mut woken: RemovedComponents<Sleeping>,
+    mut dice: Query<&mut AutoSleep, (With<Die>, Without<Sleeping>)>,
+) {
+    for mut auto_sleep in slept.iter_mut() {
+        stats.record(auto_sleep.awake);
+        auto_sleep.awake = 0.0;
+    }
+    for entity in woken.read() {
+        if let Ok(mut auto_sleep) = dice.get_mut(entity) {
+            auto_sleep.awake = 0.0;
+            auto_sleep.resting = 0.0;
+        }
+    }
+}
+
+pub fn settle_panel(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<SettleSettings>,
+    mut stats: ResMut<SettleStats>,
+    camera: Single<(&Camera, &GlobalTransform)>,
+    dice: Query<(Entity, &GlobalTransform, &AutoSleep, Has<Sleeping>), With<Die>>,
+) {
+    let ctx = contexts.ctx_mut();
+    // only write back on changes, so that avian is not reconfigured every frame
+    let mut edited = *settings;
+    egui::Window::new("Settling")
+        .default_open(false)
+        .show(ctx, |ui| {
+            egui::ComboBox::from_label("Mode")
+                .selected_text(edited.mode.name())
+                .show_ui(ui, |ui| {
+                    for mode in SettleMode::ALL {
+                        ui.selectable_value(&mut edited.mode, mode, mode.name());
+                    }
+                });
+            ui.add(egui::Slider::new(&mut edited.time, 0.05..=3.0).text("Rest time"));
+            ui.add(egui::Slider::new(&mut edited.threshold, 0.01..=0.5).text("Pose threshold"));
+            ui.add(
+                egui::Slider::new(&mut edited.linear_threshold, 0.01..=1.0)
+                    .text("Linear threshold"),
+            );
+            ui.add(
+                egui::Slider::new(&mut edited.angular_threshold, 0.01..=1.0)
+                    .text("Angular threshold"),
+            );
+            ui.checkbox(&mut edited.overlay, "Show settle timers");
+            ui.separator();
+            let seconds = |time: Option<f32>| {
+                time.map_or_else(|| String::from("-"), |time| format!("{time:.2} s"))
+            };
+            ui.label(format!("settled dice: {}", stats.count()));
+            ui.label(format!("mean: {}", seconds(stats.mean())));
+            ui.label(format!("median: {}", seconds(stats.percentile(50.0))));
+            ui.label(format!(
+                "95th percentile: {}",
+                seconds(stats.percentile(95.0))
+            ));
+            if ui.button("Reset statistics").clicked() {
+                stats.clear();
+            }
+        });
+    if edited != *settings {
+        *settings = edited;
+    }
+
+    if !settings.overlay {
+        return;
+    }
+    let (camera, camera_transform) = *camera;
+    for (entity, transform, auto_sleep, sleeping) in dice.iter() {
+        let Ok(position) = camera.world_to_viewport(camera_transform, transform.translation())
+        else {
+            continue;
+        };
+        let text = if sleeping {
+            String::from("asleep")
+        } else {
+            format!("{:.2} / {:.2} s", auto_sleep.resting, settings.time)
+        };
+        egui::Area::new(egui::Id::new(("settle timer", entity)))
+            .fixed_pos(egui::pos2(position.x, position.y))
+            .interactable(false)
+            .show(ctx, |ui| ui.label(text));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resting_time_restarts_on_motion() {
+        let mut auto_sleep = AutoSleep::default();
+        assert_eq!(auto_sleep.update(Vec3::ZERO, Vec3::ZERO, 0.1, 0.1), 0.1);
+        assert_eq!(auto_sleep.update(Vec3::X * 0.05, Vec3::ZERO, 0.1, 0.1), 0.2);
+        // small changes add up, as they are measured from the last resting pose
+        assert_eq!(
+            auto_sleep.update(Vec3::X * 0.05, Vec3::Y * 0.06, 0.1, 0.1),
+            0.0
+        );
+        assert_eq!(
+            auto_sleep.update(Vec3::X * 0.05, Vec3::Y * 0.06, 0.1, 0.1),
+            0.1
+        );
+        assert!((auto_sleep.awake - 0.4).abs() < 1e-6);
+    }
+
+    #[test]
+    fn statistics_of_settle_times() {
+        let mut stats = SettleStats::default();
+        assert_eq!(stats.mean(), None);
+        assert_eq!(stats.percentile(50.0), None);
+        for time in [0.4, 0.1, 0.3, 0.2] {
+            stats.record(time);
+        }
+        assert_eq!(stats.count(), 4);
+        assert!((stats.mean().unwrap() - 0.25).abs() < 1e-6);
+        assert_eq!(stats.percentile(50.0), Some(0.2));
+        assert_eq!(stats.percentile(95.0), Some(0.4));
+        assert_eq!(stats.percentile(0.0), Some(0.1));
+    }
+
+    #[test]
+    fn settle_modes_round_trip() {
+        for mode in SettleMode::ALL {
+            assert_eq!(SettleMode::parse(mode.name()), Ok(mode));
+        }
+        assert!(SettleMode::parse("sleepy").is_err());
+    }
+}
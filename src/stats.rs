@@ -0,0 +1,249 @@
+use crate::cache::Cache;
+use crate::cli::value_of;
+use crate::die::{ColliderMode, Die, DieShape, Loaded, cached_d6};
+use crate::geometry::GeometryError;
+use crate::layout::FaceLayout;
+use crate::settle::{AutoSleep, SettlePlugin, SettleSettings};
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use bevy::scene::ScenePlugin;
+use bevy::time::TimeUpdateStrategy;
+use rand::Rng;
+use std::time::{Duration, Instant};
+
+const BATCH_SIZE: usize = 16;
+const MAX_STEPS_PER_BATCH: usize = 64 * 20;
+
+pub struct StatsConfig {
+    pub rolls: usize,
+    pub loaded: Option<Loaded>,
+    pub layout: FaceLayout,
+    pub shape: DieShape,
+    pub colliders: Vec<ColliderMode>,
+}
+
+struct Tally {
+    counts: [usize; 6],
+    unsettled: usize,
+    steps: usize,
+    elapsed: Duration,
+}
+
+impl StatsConfig {
+    // dice --stats <rolls> [--loaded <face>:<bias>] [--layout <layout>] [--shape <shape>]
+    //      [--collider <mode>|all]
+    pub fn from_args(args: &[String]) -> Option<Self> {
+        let rolls = value_of(args, "--stats")?
+            .parse()
+            .expect("--stats expects a number of rolls");
+        let loaded =
+            value_of(args, "--loaded").map(|value| Loaded::parse(value).expect("--loaded"));
+        let layout = value_of(args, "--layout")
+            .map(|value| FaceLayout::parse(value).expect("--layout"))
+            .unwrap_or_default();
+        let shape = value_of(args, "--shape")
+            .map(|value| DieShape::parse(value).expect("--shape"))
+            .unwrap_or_default();
+        let colliders = match value_of(args, "--collider") {
+            Some("all") => ColliderMode::ALL.to_vec(),
+            Some(value) => vec![ColliderMode::parse(value).expect("--collider")],
+            None => vec![ColliderMode::default()],
+        };
+        Some(StatsConfig {
+            rolls,
+            loaded,
+            layout,
+            shape,
+            colliders,
+        })
+    }
+}
+
+pub fn run(config: StatsConfig) -> Result<(), GeometryError> {
+    let mut tallies = vec![];
+    for mode in &config.colliders {
+        let tally = roll(&config, *mode)?;
+        println!("collider: {}", mode.name());
+        print_report(&config, &tally);
+        tallies.push((*mode, tally));
+    }
+    if tallies.len() > 1 {
+        print_comparison(&tallies);
+    }
+    Ok(())
+}
+
+fn roll(config: &StatsConfig, mode: ColliderMode) -> Result<Tally, GeometryError> {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        TransformPlugin,
+        AssetPlugin::default(),
+        ScenePlugin,
+        PhysicsPlugins::default(),
+        SettlePlugin,
+    ))
+    .init_asset::<Mesh>()
+    .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+        1.0 / 64.0,
+    )))
+    // dice are only read here, so one that keeps wobbling on its face counts as resting
+    .insert_resource(SettleSettings {
+        threshold: 0.25,
+        ..default()
+    });
+    app.finish();
+    app.cleanup();
+
+    // generated from scratch, so that a benchmark does not read or fill the user's cache
+    let (_, collider) = cached_d6(&Cache::disabled(), &config.layout, &config.shape, mode)?;
+    app.world_mut().spawn((
+        RigidBody::Static,
+        Collider::cuboid(40.0, 0.2, 40.0),
+        Transform::default(),
+    ));
+
+    let mut rng = rand::rng();
+    let mut tally = Tally {
+        counts: [0; 6],
+        unsettled: 0,
+        steps: 0,
+        elapsed: Duration::ZERO,
+    };
+    let mut remaining = config.rolls;
+    while remaining > 0 {
+        let batch = remaining.min(BATCH_SIZE);
+        remaining -= batch;
+
+        let mut commands = app.world_mut().commands();
+        for i in 0..batch {
+            let rotation = Quat::from_euler(
+                EulerRot::XYZ,
+                rng.random_range(0.0..std::f32::consts::TAU),
+                rng.random_range(0.0..std::f32::consts::TAU),
+                rng.random_range(0.0..std::f32::consts::TAU),
+            );
+            let angular_velocity = Vec3::new(
+                rng.random_range(-1.0..1.0),
+                rng.random_range(-1.0..1.0),
+                rng.random_range(-1.0..1.0),
+            );
+            let position = Vec3::new((i % 4) as f32 * 3.0 - 4.5, 4.0, (i / 4) as f32 * 3.0 - 4.5);
+            let mut builder = Die::builder()
+                .transform(Transform::from_translation(position).with_rotation(rotation))
+                .angular_velocity(angular_velocity * 8.0)
+                .layout(config.layout);
+            if let Some(loaded) = config.loaded {
+                builder = builder.loaded(loaded.face, loaded.bias)?;
+            }
+            // dice are read the way the game reads them, once their pose stops changing
+            builder
+                .spawn(&mut commands, collider.clone())
+                .insert(AutoSleep::default());
+        }
+        app.world_mut().flush();
+
+        let start = Instant::now();
+        for _ in 0..MAX_STEPS_PER_BATCH {
+            app.update();
+            tally.steps += 1;
+            let world = app.world_mut();
+            let awake = world
+                .query_filtered::<(), (With<Die>, Without<Sleeping>)>()
+                .iter(world)
+                .count();
+            if awake == 0 {
+                break;
+            }
+        }
+        tally.elapsed += start.elapsed();
+
+        let world = app.world_mut();
+        let dice = world
+            .query_filtered::<(Entity, &Transform, &FaceLayout, Has<Sleeping>), With<Die>>()
+            .iter(world)
+            .map(|(entity, transform, layout, sleeping)| {
+                (entity, layout.top_face(transform.rotation), sleeping)
+            })
+            .collect::<Vec<_>>();
+        for (entity, face, sleeping) in dice {
+            if sleeping {
+                tally.counts[face as usize - 1] += 1;
+            } else {
+                tally.unsettled += 1;
+            }
+            world.despawn(entity);
+        }
+    }
+
+    Ok(tally)
+}
+
+// 11.07 is the critical value for 5 degrees of freedom at p = 0.05
+fn chi_squared(counts: &[usize; 6]) -> f32 {
+    let expected = counts.iter().sum::<usize>() as f32 / 6.0;
+    counts
+        .iter()
+        .map(|count| (*count as f32 - expected).powi(2) / expected.max(f32::EPSILON))
+        .sum()
+}
+
+fn print_report(config: &StatsConfig, tally: &Tally) {
+    let total = tally.counts.iter().sum::<usize>();
+    println!("shape: {}", config.shape.name());
+    if let Some(loaded) = config.loaded {
+        println!("loaded towards {} with bias {}", loaded.face, loaded.bias);
+    }
+    println!("face  count  share");
+    for (face, count) in tally.counts.iter().enumerate() {
+        let share = *count as f32 / total.max(1) as f32;
+        println!("{:>4}  {:>5}  {:>5.1}%", face + 1, count, share * 100.0);
+    }
+    println!("rolls: {total}, unsettled: {}", tally.unsettled);
+    let chi_squared = chi_squared(&tally.counts);
+    println!(
+        "chi²: {chi_squared:.2} ({})",
+        if chi_squared > 11.07 {
+            "biased"
+        } else {
+            "fair"
+        }
+    );
+}
+
+fn print_comparison(tallies: &[(ColliderMode, Tally)]) {
+    println!("collider       steps  ms/step  unsettled   chi²");
+    for (mode, tally) in tallies {
+        let per_step = tally.elapsed.as_secs_f64() * 1000.0 / tally.steps.max(1) as f64;
+        println!(
+            "{:<13}  {:>5}  {:>7.3}  {:>9}  {:>5.2}",
+            mode.name(),
+            tally.steps,
+            per_step,
+            tally.unsettled,
+            chi_squared(&tally.counts)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loaded_dice_favor_their_face() {
+        let config = StatsConfig {
+            rolls: 96,
+            loaded: Some(Loaded { face: 6, bias: 0.8 }),
+            layout: FaceLayout::default(),
+            shape: DieShape::default(),
+            colliders: vec![ColliderMode::default()],
+        };
+        let tally = roll(&config, ColliderMode::default()).unwrap();
+        let total = tally.counts.iter().sum::<usize>();
+        // heavily loaded dice wobble on their face for a long time, not all of them settle
+        assert!(total > 48, "only {total} dice settled");
+        // a fair die shows a six on a sixth of the rolls
+        assert!(tally.counts[5] > total / 2, "{:?}", tally.counts);
+    }
+}
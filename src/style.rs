@@ -0,0 +1,476 @@
+use crate::atlas::{Marking, SelectedMarking};
+use crate::die::{Die, DieShape, Engraved, SelectedShape};
+use crate::faces::{DieFaces, SelectedFaces};
+use crate::layout::{FaceLayout, SelectedLayout};
+use crate::players::{Owner, Players};
+use bevy::prelude::*;
+use bevy::render::render_resource::TextureFormat;
+use bevy_inspector_egui::bevy_egui::{EguiContexts, egui};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Finish {
+    Resin,
+    Wood,
+    Metal,
+    Glass,
+}
+
+#[derive(Component, Clone, Copy, PartialEq, Debug)]
+pub struct DieStyle {
+    pub body: Color,
+    pub pips: Color,
+    pub finish: Finish,
+}
+
+// the color atlas is white with black markings and only serves as a mask for the tint
+#[derive(Component, Clone)]
+pub struct DieAtlas {
+    pub color: Handle<Image>,
+    pub depth: Handle<Image>,
+    pub normal: Handle<Image>,
+    pub parallax_depth_scale: f32,
+    // engraved meshes carry the relief themselves, so the maps would only carve it twice
+    pub engraved: bool,
+}
+
+// hovering swaps in a highlighted variant of the shared material instead of mutating it
+#[derive(Component, Clone, Copy, PartialEq, Default)]
+pub struct Highlighted(pub bool);
+
+type TintKey = (AssetId<Image>, [u8; 3], [u8; 3]);
+
+// dice with the same atlas, style and highlight share one material, so that they are batched
+#[derive(Resource, Default)]
+pub struct DieMaterials {
+    tinted: HashMap<TintKey, Handle<Image>>,
+    materials: HashMap<(TintKey, Finish, bool, bool), Handle<StandardMaterial>>,
+}
+
+#[derive(Component)]
+pub struct Unstyled;
+
+const HIGHLIGHT: Color = Color::srgb(0.0, 0.8, 0.8);
+
+impl Finish {
+    pub const ALL: [Finish; 4] = [Finish::Resin, Finish::Wood, Finish::Metal, Finish::Glass];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Finish::Resin => "Resin",
+            Finish::Wood => "Wood",
+            Finish::Metal => "Metal",
+            Finish::Glass => "Glass",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        Finish::ALL
+            .into_iter()
+            .find(|finish| finish.name().eq_ignore_ascii_case(value))
+            .ok_or_else(|| format!("unknown finish {value}, expected resin, wood, metal or glass"))
+    }
+
+    pub fn apply(&self, material: &mut StandardMaterial) {
+        match self {
+            Finish::Resin => {
+                material.perceptual_roughness = 0.8;
+            }
+            Finish::Wood => {
+                material.perceptual_roughness = 0.95;
+                material.reflectance = 0.2;
+            }
+            Finish::Metal => {
+                material.perceptual_roughness = 0.35;
+                material.metallic = 1.0;
+            }
+            Finish::Glass => {
+                material.perceptual_roughness = 0.05;
+                material.specular_transmission = 0.9;
+                material.thickness = 0.6;
+                material.ior = 1.5;
+            }
+        }
+    }
+}
+
+impl Default for DieStyle {
+    fn default() -> Self {
+        DieStyle {
+            body: Color::WHITE,
+            pips: Color::BLACK,
+            finish: Finish::Resin,
+        }
+    }
+}
+
+impl DieMaterials {
+    // returns None as long as the color atlas is not available or cannot be tinted
+    pub fn get(
+        &mut self,
+        atlas: &DieAtlas,
+        style: &DieStyle,
+        highlighted: bool,
+        images: &mut Assets<Image>,
+        materials: &mut Assets<StandardMaterial>,
+    ) -> Option<Handle<StandardMaterial>> {
+        let tint_key = (
+            atlas.color.id(),
+            style.body.to_srgba().to_u8_array_no_alpha(),
+            style.pips.to_srgba().to_u8_array_no_alpha(),
+        );
+        let key = (tint_key, style.finish, highlighted, atlas.engraved);
+        if let Some(material) = self.materials.get(&key) {
+            return Some(material.clone());
+        }
+        let texture = match self.tinted.get(&tint_key) {
+            Some(texture) => texture.clone(),
+            None => {
+                let tinted = tint(images.get(&atlas.color)?, style)?;
+                let texture = images.add(tinted);
+                self.tinted.insert(tint_key, texture.clone());
+                texture
+            }
+        };
+        let mut material = StandardMaterial {
+            base_color: if highlighted { HIGHLIGHT } else { Color::WHITE },
+            base_color_texture: Some(texture),
+            normal_map_texture: (!atlas.engraved).then(|| atlas.normal.clone()),
+            depth_map: (!atlas.engraved).then(|| atlas.depth.clone()),
+            parallax_depth_scale: atlas.parallax_depth_scale,
+            ..default()
+        };
+        style.finish.apply(&mut material);
+        let material = materials.add(material);
+        self.materials.insert(key, material.clone());
+        Some(material)
+    }
+}
+
+// the red channel of the mask mixes from the pips to the body color; only the format the
+// atlases are generated in is supported
+fn tint(source: &Image, style: &DieStyle) -> Option<Image> {
+    if source.texture_descriptor.format != TextureFormat::Rgba8UnormSrgb {
+        return None;
+    }
+    let body = style.body.to_srgba().to_u8_array_no_alpha();
+    let pips = style.pips.to_srgba().to_u8_array_no_alpha();
+    let colors = (0..=255)
+        .map(|mask| {
+            let mix = |pips: u8, body: u8| {
+                (pips as f32 + (body as f32 - pips as f32) * mask as f32 / 255.0).round() as u8
+            };
+            [
+                mix(pips[0], body[0]),
+                mix(pips[1], body[1]),
+                mix(pips[2], body[2]),
+                255,
+            ]
+        })
+        .collect::<Vec<_>>();
+    let data = source
+        .data
+        .as_ref()?
+        .chunks_exact(4)
+        .flat_map(|pixel| colors[pixel[0] as usize])
+        .collect();
+    let mut tinted = source.clone();
+    tinted.data = Some(data);
+    Some(tinted)
+}
+
+// dragging a color slider passes many colors, the materials and tints no die uses any more are
+// dropped again, which frees their assets once the handles are gone
+pub fn evict_die_materials(
+    mut die_materials: ResMut<DieMaterials>,
+    dice: Query<&MeshMaterial3d<StandardMaterial>, With<Die>>,
+    changed: Query<(), (With<Die>, Changed<MeshMaterial3d<StandardMaterial>>)>,
+    mut removed: RemovedComponents<Die>,
+) {
+    if changed.is_empty() && removed.read().count() == 0 {
+        return;
+    }
+    let used = dice
+        .iter()
+        .map(|material| material.id())
+        .collect::<HashSet<_>>();
+    let die_materials = &mut *die_materials;
+    die_materials
+        .materials
+        .retain(|_, material| used.contains(&material.id()));
+    let tints = die_materials
+        .materials
+        .keys()
+        .map(|(tint_key, ..)| *tint_key)
+        .collect::<HashSet<_>>();
+    die_materials
+        .tinted
+        .retain(|tint_key, _| tints.contains(tint_key));
+}
+
+// only dice whose style or highlight changed are switched to another shared material
+#[allow(clippy::type_complexity)]
+pub fn style_dice(
+    mut commands: Commands,
+    mut query: Query<
+        (
+            Entity,
+            &DieStyle,
+            &DieAtlas,
+            &Highlighted,
+            &mut MeshMaterial3d<StandardMaterial>,
+            Has<Unstyled>,
+        ),
+        Or<(Changed<DieStyle>, Changed<Highlighted>, With<Unstyled>)>,
+    >,
+    mut die_materials: ResMut<DieMaterials>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for (entity, style, atlas, highlighted, mut mesh_material, unstyled) in query.iter_mut() {
+        match die_materials.get(atlas, style, highlighted.0, &mut images, &mut materials) {
+            Some(material) => {
+                mesh_material.0 = material;
+                if unstyled {
+                    commands.entity(entity).remove::<Unstyled>();
+                }
+            }
+            None => {
+                commands.entity(entity).insert(Unstyled);
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn style_picker(
+    mut contexts: EguiContexts,
+    mut faces: ResMut<SelectedFaces>,
+    mut marking: ResMut<SelectedMarking>,
+    mut shape: ResMut<SelectedShape>,
+    mut layout: ResMut<SelectedLayout>,
+    mut engraved: ResMut<Engraved>,
+    mut players: ResMut<Players>,
+    mut dice: Query<(&mut DieStyle, &Owner), With<Die>>,
+    mut custom_faces: Local<String>,
+) {
+    // the picker edits the active player's style, which is only written back on changes
+    let mut edited = players.active().style;
+    let style = &mut edited;
+    egui::Window::new("Die style").show(contexts.ctx_mut(), |ui| {
+        let mut body = style.body.to_srgba().to_u8_array_no_alpha();
+        let mut pips = style.pips.to_srgba().to_u8_array_no_alpha();
+        ui.horizontal(|ui| {
+            ui.label("Body");
+            ui.color_edit_button_srgb(&mut body);
+            ui.label("Pips");
+            ui.color_edit_button_srgb(&mut pips);
+        });
+        style.body = Color::srgb_u8(body[0], body[1], body[2]);
+        style.pips = Color::srgb_u8(pips[0], pips[1], pips[2]);
+        egui::ComboBox::from_label("Finish")
+            .selected_text(style.finish.name())
+            .show_ui(ui, |ui| {
+                for finish in Finish::ALL {
+                    ui.selectable_value(&mut style.finish, finish, finish.name());
+                }
+            });
+        egui::ComboBox::from_label("Faces")
+            .selected_text(faces.0.name.as_str())
+            .show_ui(ui, |ui| {
+                for preset in DieFaces::presets() {
+                    let selected = preset.name == faces.0.name;
+                    if ui
+                        .selectable_label(selected, preset.name.as_str())
+                        .clicked()
+                    {
+                        faces.0 = preset;
+                    }
+                }
+            });
+        // numbers up to 9 are drawn as pips or numerals, other labels are always text
+        egui::ComboBox::from_label("Marking")
+            .selected_text(marking.0.name())
+            .show_ui(ui, |ui| {
+                for option in Marking::ALL {
+                    if ui
+                        .selectable_label(option == marking.0, option.name())
+                        .clicked()
+                    {
+                        marking.0 = option;
+                    }
+                }
+            });
+        // six comma separated labels, applied once they are entered
+        ui.horizontal(|ui| {
+            ui.label("Custom faces");
+            let edit = ui.text_edit_singleline(&mut *custom_faces);
+            if edit.lost_focus() && ui.input(|input| input.key_pressed(egui::Key::Enter)) {
+                match DieFaces::parse(&custom_faces) {
+                    Ok(custom) => faces.0 = custom,
+                    Err(error) => warn!("{error}"),
+                }
+            }
+        });
+        // like the shape, the layout only applies to new dice
+        egui::ComboBox::from_label("Layout")
+            .selected_text(layout.0.name())
+            .show_ui(ui, |ui| {
+                for preset in FaceLayout::PRESETS {
+                    if ui
+                        .selectable_label(preset == layout.0, preset.name())
+                        .clicked()
+                    {
+                        layout.0 = preset;
+                    }
+                }
+            });
+        // the shape only applies to new dice, as it changes their colliders
+        egui::ComboBox::from_label("Shape")
+            .selected_text(shape.0.name())
+            .show_ui(ui, |ui| {
+                for preset in DieShape::PRESETS {
+                    let selected = preset.name() == shape.0.name();
+                    if ui.selectable_label(selected, preset.name()).clicked() {
+                        shape.0 = preset;
+                    }
+                }
+            });
+        if let DieShape::Rounded {
+            edge_radius,
+            corner_radius,
+        } = &mut shape.0
+        {
+            ui.add(egui::Slider::new(edge_radius, 0.0..=0.5).text("Edge radius"));
+            ui.add(egui::Slider::new(corner_radius, 0.0..=0.5).text("Corner radius"));
+            *corner_radius = corner_radius.max(*edge_radius);
+        }
+        ui.checkbox(&mut engraved.0, "Engraved");
+        // other players keep the style of their dice
+        if ui.button("Apply style to all my dice").clicked() {
+            for (mut die_style, owner) in dice.iter_mut() {
+                if owner.0 == players.active {
+                    *die_style = *style;
+                }
+            }
+        }
+    });
+    if edited != players.active().style {
+        let active = players.active;
+        players.list[active].style = edited;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atlas::AtlasSettings;
+    use bevy::asset::RenderAssetUsages;
+    use bevy::render::render_resource::{Extent3d, TextureDimension};
+
+    fn die_atlas(images: &mut Assets<Image>) -> DieAtlas {
+        let atlas = AtlasSettings {
+            resolution: 8,
+            ..default()
+        }
+        .generate(&["1", "2"]);
+        DieAtlas {
+            color: images.add(atlas.color),
+            depth: images.add(atlas.depth),
+            normal: images.add(atlas.normal),
+            parallax_depth_scale: atlas.parallax_depth_scale,
+            engraved: false,
+        }
+    }
+
+    #[test]
+    fn masks_are_tinted() {
+        let mask = Image::new(
+            Extent3d {
+                width: 2,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            vec![0, 0, 0, 255, 255, 255, 255, 255],
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::default(),
+        );
+        let style = DieStyle {
+            body: Color::srgb_u8(200, 100, 0),
+            pips: Color::srgb_u8(0, 50, 250),
+            finish: Finish::Resin,
+        };
+        let tinted = tint(&mask, &style).unwrap();
+        assert_eq!(tinted.data, Some(vec![0, 50, 250, 255, 200, 100, 0, 255]));
+        let mut depth = mask.clone();
+        depth.texture_descriptor.format = TextureFormat::R8Unorm;
+        assert!(tint(&depth, &style).is_none());
+    }
+
+    #[test]
+    fn dice_with_the_same_look_share_a_material() {
+        let mut images = Assets::<Image>::default();
+        let mut materials = Assets::<StandardMaterial>::default();
+        let mut die_materials = DieMaterials::default();
+        let atlas = die_atlas(&mut images);
+        let style = DieStyle::default();
+        let mut get = |style: &DieStyle, highlighted: bool| {
+            die_materials
+                .get(&atlas, style, highlighted, &mut images, &mut materials)
+                .unwrap()
+        };
+        let material = get(&style, false);
+        assert_eq!(material, get(&style, false));
+        assert_ne!(material, get(&style, true));
+        let wood = DieStyle {
+            finish: Finish::Wood,
+            ..style
+        };
+        assert_ne!(material, get(&wood, false));
+        let red = DieStyle {
+            body: Color::srgb(1.0, 0.0, 0.0),
+            ..style
+        };
+        assert_ne!(material, get(&red, false));
+        // the finish and the highlight are material settings, only the colors need a texture
+        assert_eq!(die_materials.materials.len(), 4);
+        assert_eq!(die_materials.tinted.len(), 2);
+    }
+
+    #[test]
+    fn unused_materials_are_evicted() {
+        let mut app = App::new();
+        app.init_resource::<Assets<Image>>()
+            .init_resource::<Assets<StandardMaterial>>()
+            .init_resource::<DieMaterials>()
+            .add_systems(Update, evict_die_materials);
+        let world = app.world_mut();
+        let atlas = die_atlas(&mut world.resource_mut::<Assets<Image>>());
+        let styles = [
+            DieStyle::default(),
+            DieStyle {
+                body: Color::srgb(1.0, 0.0, 0.0),
+                ..default()
+            },
+        ];
+        let dice = styles.map(|style| {
+            let material = world.resource_scope(|world, mut die_materials: Mut<DieMaterials>| {
+                world.resource_scope(|world, mut materials: Mut<Assets<StandardMaterial>>| {
+                    let mut images = world.resource_mut::<Assets<Image>>();
+                    die_materials
+                        .get(&atlas, &style, false, &mut images, &mut materials)
+                        .unwrap()
+                })
+            });
+            world.spawn((Die, MeshMaterial3d(material))).id()
+        });
+        app.update();
+        assert_eq!(app.world().resource::<DieMaterials>().materials.len(), 2);
+        app.world_mut().despawn(dice[1]);
+        app.update();
+        let die_materials = app.world().resource::<DieMaterials>();
+        assert_eq!(die_materials.materials.len(), 1);
+        assert_eq!(die_materials.tinted.len(), 1);
+    }
+}
@@ -0,0 +1,301 @@
+use crate::die::{Die, GRAVITY_SCALE};
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{EguiContexts, egui};
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+#[derive(Resource, Clone, PartialEq, Debug)]
+pub struct PhysicsTuning {
+    pub name: String,
+    pub gravity_scale: f32,
+    pub restitution: f32,
+    pub friction: f32,
+    pub linear_damping: f32,
+    pub angular_damping: f32,
+    // how hard the cup chases the cursor and how fast it tilts towards the table center
+    pub cup_chase_gain: f32,
+    pub cup_tilt_gain: f32,
+    // new dice spin with up to this many radians per second around every axis
+    pub spawn_angular_velocity: f32,
+}
+
+// presets saved from the panel, one <name>.tuning file each
+#[derive(Resource)]
+pub struct TuningPresets {
+    dir: Option<PathBuf>,
+    saved: Vec<PhysicsTuning>,
+}
+
+impl Default for PhysicsTuning {
+    fn default() -> Self {
+        PhysicsTuning {
+            name: String::from("Default"),
+            gravity_scale: GRAVITY_SCALE,
+            restitution: 0.4,
+            friction: 0.5,
+            linear_damping: 0.0,
+            angular_damping: 0.0,
+            cup_chase_gain: 8.0,
+            cup_tilt_gain: 4.0,
+            spawn_angular_velocity: 8.0,
+        }
+    }
+}
+
+impl PhysicsTuning {
+    pub fn presets() -> Vec<PhysicsTuning> {
+        vec![
+            PhysicsTuning::default(),
+            // dice barely bounce and stop quickly on the cloth
+            PhysicsTuning {
+                name: String::from("Casino felt"),
+                restitution: 0.15,
+                friction: 0.9,
+                linear_damping: 0.5,
+                angular_damping: 1.5,
+                ..default()
+            },
+            PhysicsTuning {
+                name: String::from("Wooden table"),
+                restitution: 0.55,
+                friction: 0.35,
+                linear_damping: 0.1,
+                angular_damping: 0.2,
+                ..default()
+            },
+            // a sixth of the usual gravity, with a gentler cup to match
+            PhysicsTuning {
+                name: String::from("Low gravity moon"),
+                gravity_scale: GRAVITY_SCALE / 6.0,
+                cup_chase_gain: 4.0,
+                cup_tilt_gain: 2.0,
+                spawn_angular_velocity: 4.0,
+                ..default()
+            },
+        ]
+    }
+
+    // the key, the range offered by the panel and the value of every field, in file order
+    fn fields(&mut self) -> [(&'static str, RangeInclusive<f32>, &mut f32); 8] {
+        [
+            ("gravity_scale", 0.5..=40.0, &mut self.gravity_scale),
+            ("restitution", 0.0..=1.0, &mut self.restitution),
+            ("friction", 0.0..=2.0, &mut self.friction),
+            ("linear_damping", 0.0..=5.0, &mut self.linear_damping),
+            ("angular_damping", 0.0..=5.0, &mut self.angular_damping),
+            ("cup_chase_gain", 1.0..=20.0, &mut self.cup_chase_gain),
+            ("cup_tilt_gain", 0.5..=10.0, &mut self.cup_tilt_gain),
+            (
+                "spawn_angular_velocity",
+                0.0..=20.0,
+                &mut self.spawn_angular_velocity,
+            ),
+        ]
+    }
+
+    // the physics components of a die, which replace the ones the builder inserted
+    pub fn die_components(&self) -> impl Bundle {
+        (
+            GravityScale(self.gravity_scale),
+            Restitution::new(self.restitution),
+            Friction::new(self.friction),
+            LinearDamping(self.linear_damping),
+            AngularDamping(self.angular_damping),
+        )
+    }
+
+    // one `key = value` per line, fields that are left out keep their default
+    pub fn parse(name: &str, text: &str) -> Result<Self, String> {
+        let mut tuning = PhysicsTuning {
+            name: String::from(name),
+            ..default()
+        };
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("expected <key> = <value>, got {line}"))?;
+            let (key, value) = (key.trim(), value.trim());
+            let mut fields = tuning.fields();
+            let (_, _, field) = fields
+                .iter_mut()
+                .find(|(name, _, _)| *name == key)
+                .ok_or_else(|| format!("unknown key {key}"))?;
+            **field = value
+                .parse()
+                .map_err(|_| format!("{key} must be a number, got {value}"))?;
+        }
+        Ok(tuning)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut tuning = self.clone();
+        tuning
+            .fields()
+            .iter()
+            .map(|(key, _, value)| format!("{key} = {value}\n"))
+            .collect()
+    }
+
+    fn file_name(&self) -> Option<String> {
+        let name = self
+            .name
+            .chars()
+            .filter(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_'))
+            .collect::<String>();
+        let name = name.trim();
+        (!name.is_empty()).then(|| format!("{name}.tuning"))
+    }
+}
+
+impl TuningPresets {
+    // saved next to the other settings, in $XDG_CONFIG_HOME/dice/tuning
+    pub fn from_env() -> Self {
+        let dir = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .map(|dir| dir.join("dice").join("tuning"));
+        let mut presets = TuningPresets { dir, saved: vec![] };
+        presets.load();
+        presets
+    }
+
+    // files that cannot be read are skipped with a warning
+    fn load(&mut self) {
+        self.saved.clear();
+        let Some(entries) = self.dir.as_ref().and_then(|dir| fs::read_dir(dir).ok()) else {
+            return;
+        };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path
+                .extension()
+                .is_none_or(|extension| extension != "tuning")
+            {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            match fs::read_to_string(&path)
+                .map_err(|error| error.to_string())
+                .and_then(|text| PhysicsTuning::parse(name, &text))
+            {
+                Ok(tuning) => self.saved.push(tuning),
+                Err(error) => warn!("could not load the tuning {path:?}: {error}"),
+            }
+        }
+        self.saved.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    fn save(&mut self, tuning: &PhysicsTuning) -> Result<(), String> {
+        let dir = self.dir.as_ref().ok_or("there is no config directory")?;
+        let file_name = tuning
+            .file_name()
+            .ok_or("the name needs at least one letter or digit")?;
+        fs::create_dir_all(dir)
+            .and_then(|_| fs::write(dir.join(file_name), tuning.to_text()))
+            .map_err(|error| error.to_string())?;
+        self.load();
+        Ok(())
+    }
+}
+
+pub fn apply_tuning(
+    mut commands: Commands,
+    tuning: Res<PhysicsTuning>,
+    dice: Query<Entity, With<Die>>,
+) {
+    for entity in dice.iter() {
+        commands.entity(entity).insert(tuning.die_components());
+    }
+}
+
+pub fn tuning_panel(
+    mut contexts: EguiContexts,
+    mut tuning: ResMut<PhysicsTuning>,
+    mut presets: ResMut<TuningPresets>,
+    mut name: Local<String>,
+) {
+    // only write back on changes, so that the dice are not updated every frame
+    let mut edited = tuning.clone();
+    egui::Window::new("Physics")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::ComboBox::from_label("Preset")
+                .selected_text(edited.name.as_str())
+                .show_ui(ui, |ui| {
+                    for preset in PhysicsTuning::presets()
+                        .into_iter()
+                        .chain(presets.saved.clone())
+                    {
+                        if ui
+                            .selectable_label(preset == edited, preset.name.as_str())
+                            .clicked()
+                        {
+                            edited = preset;
+                        }
+                    }
+                });
+            for (key, range, value) in edited.fields() {
+                ui.add(egui::Slider::new(value, range).text(key.replace('_', " ")));
+            }
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut *name);
+                if ui.button("Save preset").clicked() {
+                    edited.name = name.clone();
+                    if let Err(error) = presets.save(&edited) {
+                        warn!("could not save the tuning {}: {error}", edited.name);
+                    }
+                }
+            });
+        });
+    if edited != *tuning {
+        *tuning = edited;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_survive_a_round_trip() {
+        for preset in PhysicsTuning::presets() {
+            let parsed = PhysicsTuning::parse(&preset.name, &preset.to_text()).unwrap();
+            assert_eq!(parsed, preset);
+        }
+    }
+
+    #[test]
+    fn missing_fields_keep_their_default() {
+        let text = "# only the bounce\n\nrestitution = 0.9\n";
+        let tuning = PhysicsTuning::parse("Bouncy", text).unwrap();
+        assert_eq!(tuning.restitution, 0.9);
+        assert_eq!(tuning.gravity_scale, GRAVITY_SCALE);
+        assert_eq!(tuning.name, "Bouncy");
+    }
+
+    #[test]
+    fn invalid_lines_are_rejected() {
+        for text in ["bounce = 1", "restitution = high", "restitution 0.5"] {
+            assert!(PhysicsTuning::parse("Broken", text).is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn file_names_only_keep_safe_characters() {
+        let tuning = |name: &str| PhysicsTuning {
+            name: String::from(name),
+            ..default()
+        };
+        assert_eq!(
+            tuning("../Casino felt!").file_name().as_deref(),
+            Some("Casino felt.tuning")
+        );
+        assert_eq!(tuning(" /. ").file_name(), None);
+    }
+}
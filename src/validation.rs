@@ -0,0 +1,223 @@
+use crate::geometry::{GeometryError, extract_mesh_attributes};
+use bevy::prelude::*;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+// edges are reported with welded vertex indices, so seams that only exist
+// for the sake of uvs do not show up as holes
+#[derive(Debug, Default)]
+pub struct MeshReport {
+    pub open_edges: Vec<(usize, usize)>,
+    pub non_manifold_edges: Vec<(usize, usize)>,
+    pub inconsistent_edges: Vec<(usize, usize)>,
+    pub degenerate_triangles: Vec<usize>,
+    pub duplicate_vertices: Vec<(usize, usize)>,
+}
+
+impl MeshReport {
+    pub fn is_watertight(&self) -> bool {
+        self.open_edges.is_empty() && self.non_manifold_edges.is_empty()
+    }
+
+    // duplicate vertices are allowed, as they are needed for uv seams
+    pub fn is_valid(&self) -> bool {
+        self.is_watertight()
+            && self.inconsistent_edges.is_empty()
+            && self.degenerate_triangles.is_empty()
+    }
+}
+
+impl Display for MeshReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} open edges, {} non-manifold edges, {} inconsistent edges, \
+             {} degenerate triangles, {} duplicate vertices",
+            self.open_edges.len(),
+            self.non_manifold_edges.len(),
+            self.inconsistent_edges.len(),
+            self.degenerate_triangles.len(),
+            self.duplicate_vertices.len()
+        )
+    }
+}
+
+pub fn validate_mesh(mesh: &Mesh, epsilon: f32) -> Result<MeshReport, GeometryError> {
+    let (vertices, indices) = extract_mesh_attributes(mesh)?;
+    let mut report = MeshReport::default();
+
+    let mut welded = Vec::with_capacity(vertices.len());
+    let mut cells = HashMap::new();
+    for (index, vertex) in vertices.iter().enumerate() {
+        let cell = vertex.map(|c| (c / epsilon).round() as i64);
+        let canonical = *cells.entry(cell).or_insert(index);
+        if canonical != index {
+            report.duplicate_vertices.push((canonical, index));
+        }
+        welded.push(canonical);
+    }
+
+    let mut directed_edges = HashMap::new();
+    for (triangle, corners) in indices.chunks(3).enumerate() {
+        let [a, b, c] = [0, 1, 2].map(|i| welded[corners[i]]);
+        let [pa, pb, pc] = [a, b, c].map(|i| Vec3::from_array(vertices[i]));
+        if a == b || b == c || c == a || (pb - pa).cross(pc - pa).length() <= epsilon * epsilon {
+            report.degenerate_triangles.push(triangle);
+            continue;
+        }
+        for edge in [(a, b), (b, c), (c, a)] {
+            *directed_edges.entry(edge).or_insert(0) += 1;
+        }
+    }
+
+    let mut undirected_edges = HashMap::new();
+    for (&(a, b), &count) in &directed_edges {
+        *undirected_edges.entry((a.min(b), a.max(b))).or_insert(0) += count;
+        if count > 1 {
+            report.inconsistent_edges.push((a.min(b), a.max(b)));
+        }
+    }
+    for (edge, count) in undirected_edges {
+        match count {
+            1 => report.open_edges.push(edge),
+            2 => {}
+            _ => report.non_manifold_edges.push(edge),
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atlas::{AtlasSettings, Marking};
+    use crate::geometry::{create_d6, create_icosphere, create_rounded_d6, engrave};
+    use crate::layout::FaceLayout;
+    use bevy::asset::RenderAssetUsages;
+    use bevy::render::mesh::{Indices, PrimitiveTopology};
+
+    fn with_indices(mesh: &Mesh, indices: Vec<u32>) -> Mesh {
+        Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::default(),
+        )
+        .with_inserted_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+                .expect("positions")
+                .clone(),
+        )
+        .with_inserted_indices(Indices::U32(indices))
+    }
+
+    fn indices(mesh: &Mesh) -> Vec<u32> {
+        mesh.indices()
+            .expect("indices")
+            .iter()
+            .map(|i| i as u32)
+            .collect()
+    }
+
+    #[test]
+    fn icosphere_is_valid() {
+        for iterations in 0..=4 {
+            let report = validate_mesh(&create_icosphere(iterations).unwrap(), 1e-5).unwrap();
+            assert!(report.is_valid(), "{iterations}: {report:?}");
+            assert!(report.duplicate_vertices.is_empty());
+        }
+    }
+
+    #[test]
+    fn d6_is_valid_over_parameter_sweep() {
+        for depth in 2..=4 {
+            for threshold in [0.72, 0.75, 0.8, 0.85, 0.9, 0.95] {
+                for size in [0.1, 0.6, 2.0] {
+                    let d6 =
+                        create_d6(depth, threshold, size, FaceLayout::WESTERN.values()).unwrap();
+                    let report = validate_mesh(&d6, size * 1e-5).unwrap();
+                    assert!(report.is_valid(), "{depth} {threshold} {size}: {report:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rounded_d6_is_valid_over_parameter_sweep() {
+        for segments in [1, 2, 4, 8] {
+            for radii in [
+                (0.0, 0.0),
+                (0.05, 0.05),
+                (0.0, 0.1),
+                (0.06, 0.12),
+                (0.3, 0.3),
+            ] {
+                for scale in [0.1, 1.0, 4.0] {
+                    let radii = (radii.0 * scale, radii.1 * scale);
+                    let d6 = create_rounded_d6(
+                        segments,
+                        radii,
+                        0.6 * scale,
+                        FaceLayout::WESTERN.values(),
+                    )
+                    .unwrap();
+                    let report = validate_mesh(&d6, scale * 1e-5).unwrap();
+                    assert!(report.is_valid(), "{segments} {radii:?}: {report:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn engraved_d6_is_valid() {
+        let labels = ["1", "2", "3", "4", "5", "12"];
+        for marking in [Marking::Pips, Marking::Numerals] {
+            let atlas = AtlasSettings {
+                marking,
+                ..default()
+            };
+            for d6 in [
+                create_d6(3, 0.72, 0.6, FaceLayout::WESTERN.values()).unwrap(),
+                create_rounded_d6(4, (0.05, 0.1), 0.6, FaceLayout::WESTERN.values()).unwrap(),
+                create_rounded_d6(1, (0.0, 0.0), 0.6, FaceLayout::WESTERN.values()).unwrap(),
+            ] {
+                let engraved =
+                    engrave(&d6, 0.025, |uv| atlas.depth_at(&labels, uv) * 0.02).unwrap();
+                let report = validate_mesh(&engraved, 1e-6).unwrap();
+                assert!(report.is_valid(), "{marking:?}: {report}");
+            }
+        }
+    }
+
+    #[test]
+    fn detects_holes() {
+        let sphere = create_icosphere(1).unwrap();
+        let mut indices = indices(&sphere);
+        indices.truncate(indices.len() - 3);
+        let report = validate_mesh(&with_indices(&sphere, indices), 1e-5).unwrap();
+        assert_eq!(report.open_edges.len(), 3);
+        assert!(!report.is_watertight());
+    }
+
+    #[test]
+    fn detects_flipped_triangles() {
+        let sphere = create_icosphere(1).unwrap();
+        let mut indices = indices(&sphere);
+        indices.swap(0, 1);
+        let report = validate_mesh(&with_indices(&sphere, indices), 1e-5).unwrap();
+        assert_eq!(report.inconsistent_edges.len(), 3);
+        assert!(report.is_watertight());
+        assert!(!report.is_valid());
+    }
+
+    #[test]
+    fn detects_degenerate_triangles_and_non_manifold_edges() {
+        let sphere = create_icosphere(0).unwrap();
+        let mut indices = indices(&sphere);
+        let (a, b) = (indices[0], indices[1]);
+        indices.extend([a, b, a]);
+        indices.extend([a, b, indices[5]]);
+        let report = validate_mesh(&with_indices(&sphere, indices), 1e-5).unwrap();
+        assert_eq!(report.degenerate_triangles, vec![20]);
+        assert!(!report.non_manifold_edges.is_empty());
+    }
+}